@@ -0,0 +1,138 @@
+//! Compares the memory and speed of generating a batch against the
+//! `HashSet<String>` approach the generator used before tickets were packed.
+//!
+//! Also times a batch kept at a minimum distance, which the generator
+//! checks against an index of ticket segments.
+//!
+//! Run with `cargo bench --bench uniqueness`, optionally followed by
+//! `-- <ticket count>` (5 million by default).
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashSet,
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use ticket_gen::{Generator, TicketSpec};
+
+/// Tracks live and peak heap usage.
+struct CountingAllocator;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const CHARACTER_SET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const TICKET_LENGTH: usize = 10;
+const DISTANCE_COUNT: usize = 100_000;
+const DISTANCE_LENGTH: usize = 8;
+const MIN_DISTANCE: usize = 3;
+
+fn main() {
+    let count = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(5_000_000);
+
+    println!("{count} tickets of {TICKET_LENGTH} capitals and numbers");
+    report("String set", measure(|| string_set(count)));
+    report("packed set", measure(|| packed_set(count)));
+
+    println!(
+        "{DISTANCE_COUNT} tickets of {DISTANCE_LENGTH} capitals and numbers, at least {MIN_DISTANCE} apart"
+    );
+    report("distance", measure(distance));
+}
+
+/// One fresh `String` per candidate, remembered in a `HashSet<String>`.
+fn string_set(count: usize) {
+    let character_set: Vec<char> = CHARACTER_SET.chars().collect();
+    let mut rng = ChaCha20Rng::seed_from_u64(42);
+    let mut issued = HashSet::with_capacity(count);
+    while issued.len() < count {
+        let ticket: String = (0..TICKET_LENGTH)
+            .map(|_| character_set[rng.random_range(0..character_set.len())])
+            .collect();
+        if !issued.contains(&ticket) {
+            black_box(&ticket);
+            issued.insert(ticket);
+        }
+    }
+}
+
+/// The library generator writing into one reused buffer.
+fn packed_set(count: usize) {
+    let spec = TicketSpec {
+        capital_letters: true,
+        numbers: true,
+        ticket_length: TICKET_LENGTH,
+        ticket_count: count,
+        seed: Some(42),
+        ..Default::default()
+    };
+
+    let mut generator = Generator::new(&spec).unwrap();
+    let mut buf = String::new();
+    while let Some(result) = generator.next_into(&mut buf) {
+        result.unwrap();
+        black_box(&buf);
+    }
+}
+
+/// The library generator keeping every ticket [`MIN_DISTANCE`] apart.
+fn distance() {
+    let spec = TicketSpec {
+        capital_letters: true,
+        numbers: true,
+        ticket_length: DISTANCE_LENGTH,
+        ticket_count: DISTANCE_COUNT,
+        min_distance: MIN_DISTANCE,
+        seed: Some(42),
+        ..Default::default()
+    };
+
+    let mut generator = Generator::new(&spec).unwrap();
+    let mut buf = String::new();
+    while let Some(result) = generator.next_into(&mut buf) {
+        result.unwrap();
+        black_box(&buf);
+    }
+}
+
+fn measure(run: impl FnOnce()) -> (Duration, usize) {
+    let baseline = LIVE.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let start = Instant::now();
+    run();
+    (start.elapsed(), PEAK.load(Ordering::Relaxed) - baseline)
+}
+
+fn report(name: &str, (elapsed, peak): (Duration, usize)) {
+    println!(
+        "{name:>12}: {:>8.2?}, {:>6.1} MiB peak heap",
+        elapsed,
+        peak as f64 / (1024.0 * 1024.0)
+    );
+}
@@ -0,0 +1,449 @@
+//! Aztec codes, holding the ticket's bytes in a single binary shift block.
+
+use crate::{barcode::Modules, reed_solomon::Field};
+
+/// Upper mode's binary shift code, which the symbol starts in.
+const BINARY_SHIFT: u32 = 31;
+/// The most bytes one binary shift block holds.
+const MAX_SHIFT_BYTES: usize = 2047 + 31;
+/// The most layers a full size symbol has.
+const MAX_LAYERS: usize = 32;
+/// The share of the symbol spent on check words, on top of a fixed 11 bits,
+/// as a percentage of the data.
+const CHECK_PERCENT: usize = 33;
+
+/// Message bits, most significant first.
+#[derive(Default)]
+struct Bits(Vec<bool>);
+
+impl Bits {
+    fn push(&mut self, value: u32, count: usize) {
+        self.0
+            .extend((0..count).rev().map(|bit| value >> bit & 1 == 1));
+    }
+
+    fn words(&self, word_size: usize) -> Vec<u16> {
+        self.0
+            .chunks(word_size)
+            .map(|chunk| chunk.iter().fold(0, |word, &bit| word << 1 | bit as u16))
+            .collect()
+    }
+}
+
+/// Bits per codeword in a symbol with `layers` layers.
+fn word_size(layers: usize) -> usize {
+    match layers {
+        0..=2 => 6,
+        3..=8 => 8,
+        9..=22 => 10,
+        _ => 12,
+    }
+}
+
+/// The field of `word_size` bit codewords.
+fn field(word_size: usize) -> Field {
+    match word_size {
+        4 => Field::new(0x13, 16),
+        6 => Field::new(0x43, 64),
+        8 => Field::new(0x12D, 256),
+        10 => Field::new(0x409, 1024),
+        _ => Field::new(0x1069, 4096),
+    }
+}
+
+/// Bits of the binary shift block holding `bytes` bytes.
+fn data_bits(bytes: usize) -> usize {
+    let length_bits = if bytes <= 31 { 5 } else { 16 };
+    5 + length_bits + 8 * bytes
+}
+
+/// Check bits added to `data_bits` bits of data.
+fn check_bits(data_bits: usize) -> usize {
+    data_bits * CHECK_PERCENT / 100 + 11
+}
+
+/// The most bytes of any content the largest symbol holds. Stuffing adds at
+/// most one bit for every `word_size - 1` bits of data, so this assumes it
+/// does for every word.
+pub(crate) fn max_bytes() -> usize {
+    let total_bits = layer_bits(MAX_LAYERS, false);
+    let word_size = word_size(MAX_LAYERS);
+    let usable_bits = total_bits - total_bits % word_size;
+
+    (0..=MAX_SHIFT_BYTES)
+        .rev()
+        .find(|&bytes| {
+            let data_bits = data_bits(bytes);
+            let stuffed_bits = data_bits.div_ceil(word_size - 1) * word_size;
+            stuffed_bits + check_bits(data_bits) <= usable_bits
+        })
+        .unwrap_or(0)
+}
+
+/// Bits that fit in the data layers of a symbol.
+fn layer_bits(layers: usize, compact: bool) -> usize {
+    ((if compact { 88 } else { 112 }) + 16 * layers) * layers
+}
+
+/// Splits `bits` into codewords, flipping the last bit of any word that
+/// would be all zeros or all ones into a word of its own, and pads the last
+/// word with ones.
+fn stuff(bits: &Bits, word_size: usize) -> Bits {
+    let mut stuffed = Bits::default();
+    let mask = (1 << word_size) - 2;
+    let mut i = 0;
+    while i < bits.0.len() {
+        let mut word = 0;
+        for j in 0..word_size {
+            if bits.0.get(i + j).is_none_or(|&bit| bit) {
+                word |= 1 << (word_size - 1 - j);
+            }
+        }
+        if word & mask == mask {
+            stuffed.push(word & mask, word_size);
+            i += word_size - 1;
+        } else if word & mask == 0 {
+            stuffed.push(word | 1, word_size);
+            i += word_size - 1;
+        } else {
+            stuffed.push(word, word_size);
+            i += word_size;
+        }
+    }
+
+    stuffed
+}
+
+/// `data` followed by check words filling `total_bits`, right aligned.
+fn with_check_words(data: &Bits, total_bits: usize, word_size: usize) -> Bits {
+    let words = data.words(word_size);
+    let check_count = total_bits / word_size - words.len();
+    let check_words = field(word_size).check_words(&words, check_count);
+
+    let mut bits = Bits::default();
+    bits.push(0, total_bits % word_size);
+    for word in words.into_iter().chain(check_words) {
+        bits.push(word as u32, word_size);
+    }
+
+    bits
+}
+
+/// Draws `ticket` in the smallest symbol that holds it with its check words.
+pub(crate) fn encode(ticket: &str) -> Result<Modules, String> {
+    let bytes = ticket.as_bytes();
+    if bytes.len() > MAX_SHIFT_BYTES {
+        return Err(format!("{ticket} is too long for an Aztec code"));
+    }
+
+    let mut data = Bits::default();
+    data.push(BINARY_SHIFT, 5);
+    if bytes.len() <= 31 {
+        data.push(bytes.len() as u32, 5);
+    } else {
+        data.push(0, 5);
+        data.push((bytes.len() - 31) as u32, 11);
+    }
+    for &byte in bytes {
+        data.push(byte as u32, 8);
+    }
+
+    let check_bits = check_bits(data.0.len());
+    let mut chosen = None;
+    // Compact symbols have 1 to 4 layers, and full size ones 4 to 32.
+    for i in 0..=MAX_LAYERS {
+        let compact = i <= 3;
+        let layers = if compact { i + 1 } else { i };
+        let total_bits = layer_bits(layers, compact);
+        if data.0.len() + check_bits > total_bits {
+            continue;
+        }
+        let word_size = word_size(layers);
+        let stuffed = stuff(&data, word_size);
+        if compact && stuffed.0.len() > word_size * 64 {
+            continue;
+        }
+        if stuffed.0.len() + check_bits <= total_bits - total_bits % word_size {
+            chosen = Some((compact, layers, word_size, stuffed));
+            break;
+        }
+    }
+    let (compact, layers, word_size, stuffed) =
+        chosen.ok_or_else(|| format!("{ticket} is too long for an Aztec code"))?;
+
+    let message = with_check_words(&stuffed, layer_bits(layers, compact), word_size);
+    let mode = mode_message(compact, layers, stuffed.0.len() / word_size);
+
+    Ok(draw(&message, &mode, layers, compact))
+}
+
+/// The layer and data word counts, with their own check words, that go in
+/// the ring around the bullseye.
+fn mode_message(compact: bool, layers: usize, data_words: usize) -> Bits {
+    let mut mode = Bits::default();
+    if compact {
+        mode.push(layers as u32 - 1, 2);
+        mode.push(data_words as u32 - 1, 6);
+        with_check_words(&mode, 28, 4)
+    } else {
+        mode.push(layers as u32 - 1, 5);
+        mode.push(data_words as u32 - 1, 11);
+        with_check_words(&mode, 40, 4)
+    }
+}
+
+/// Lays out the message in layers spiralling out from the bullseye, with the
+/// mode message around it and, in full size symbols, the reference grid.
+fn draw(message: &Bits, mode: &Bits, layers: usize, compact: bool) -> Modules {
+    let base_size = (if compact { 11 } else { 14 }) + layers * 4;
+    let mut positions: Vec<usize> = (0..base_size).collect();
+    let size = if compact {
+        base_size
+    } else {
+        // Full size symbols skip every 16th row and column from the center
+        // for the reference grid.
+        let size = base_size + 1 + 2 * ((base_size / 2 - 1) / 15);
+        let (base_center, center) = (base_size / 2, size / 2);
+        for i in 0..base_center {
+            let offset = i + i / 15;
+            positions[base_center - i - 1] = center - offset - 1;
+            positions[base_center + i] = center + offset + 1;
+        }
+        size
+    };
+    let mut modules = Modules::new(size, size);
+    let bit = |i: usize| message.0[i];
+
+    let mut row_offset = 0;
+    for i in 0..layers {
+        let row_size = (layers - i) * 4 + if compact { 9 } else { 12 };
+        let (near, far) = (i * 2, base_size - 1 - i * 2);
+        for j in 0..row_size {
+            let column_offset = j * 2;
+            for k in 0..2 {
+                let at = row_offset + column_offset + k;
+                if bit(at) {
+                    modules.set(positions[near + k], positions[near + j]);
+                }
+                if bit(at + row_size * 2) {
+                    modules.set(positions[near + j], positions[far - k]);
+                }
+                if bit(at + row_size * 4) {
+                    modules.set(positions[far - k], positions[far - j]);
+                }
+                if bit(at + row_size * 6) {
+                    modules.set(positions[far - j], positions[near + k]);
+                }
+            }
+        }
+        row_offset += row_size * 8;
+    }
+
+    let center = size / 2;
+    if compact {
+        for i in 0..7 {
+            let offset = center - 3 + i;
+            if mode.0[i] {
+                modules.set(offset, center - 5);
+            }
+            if mode.0[i + 7] {
+                modules.set(center + 5, offset);
+            }
+            if mode.0[20 - i] {
+                modules.set(offset, center + 5);
+            }
+            if mode.0[27 - i] {
+                modules.set(center - 5, offset);
+            }
+        }
+        draw_bullseye(&mut modules, center, 5);
+    } else {
+        for i in 0..10 {
+            let offset = center - 5 + i + i / 5;
+            if mode.0[i] {
+                modules.set(offset, center - 7);
+            }
+            if mode.0[i + 10] {
+                modules.set(center + 7, offset);
+            }
+            if mode.0[29 - i] {
+                modules.set(offset, center + 7);
+            }
+            if mode.0[39 - i] {
+                modules.set(center - 7, offset);
+            }
+        }
+        draw_bullseye(&mut modules, center, 7);
+
+        let mut j = 0;
+        for _ in (0..base_size / 2 - 1).step_by(15) {
+            for k in (center & 1..size).step_by(2) {
+                modules.set(center - j, k);
+                modules.set(center + j, k);
+                modules.set(k, center - j);
+                modules.set(k, center + j);
+            }
+            j += 16;
+        }
+    }
+
+    modules
+}
+
+/// Draws the rings of the finder pattern out to `radius`, and the
+/// orientation marks at its corners.
+fn draw_bullseye(modules: &mut Modules, center: usize, radius: usize) {
+    for i in (0..radius).step_by(2) {
+        for j in center - i..=center + i {
+            modules.set(j, center - i);
+            modules.set(j, center + i);
+            modules.set(center - i, j);
+            modules.set(center + i, j);
+        }
+    }
+
+    let (low, high) = (center - radius, center + radius);
+    modules.set(low, low);
+    modules.set(low + 1, low);
+    modules.set(low, low + 1);
+    modules.set(high, low);
+    modules.set(high, low + 1);
+    modules.set(high, high - 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(bits: &Bits) -> String {
+        bits.0
+            .iter()
+            .map(|&bit| if bit { 'X' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn check_words_match_zxing() {
+        // Mode messages, then data words of a two layer compact symbol, from
+        // ZXing's Reed-Solomon tests.
+        assert_eq!(
+            field(4).check_words(&[0x5, 0x6], 5),
+            [0x3, 0x2, 0xB, 0xB, 0x7]
+        );
+        assert_eq!(
+            field(4).check_words(&[0x0, 0x0, 0x0, 0x9], 6),
+            [0xA, 0xD, 0x8, 0x6, 0x5, 0x6]
+        );
+        assert_eq!(
+            field(4).check_words(&[0x2, 0x8, 0x8, 0x7], 6),
+            [0xE, 0xC, 0xA, 0x9, 0x6, 0x8]
+        );
+        assert_eq!(
+            field(6).check_words(
+                &[0x9, 0x32, 0x1, 0x29, 0x2F, 0x2, 0x27, 0x25, 0x1, 0x1B],
+                11
+            ),
+            [0x2C, 0x2, 0xD, 0xD, 0xA, 0x16, 0x28, 0x9, 0x22, 0xA, 0x14]
+        );
+    }
+
+    #[test]
+    fn mode_messages_match_zxing() {
+        for (compact, layers, data_words, expected) in [
+            (true, 2, 29, ".X .XXX.. ...X XX.. ..X .XX. .XX.X"),
+            (true, 4, 64, "XX XXXXXX .X.. ...X ..XX .X.. XX.."),
+            (
+                false,
+                21,
+                660,
+                "X.X.. .X.X..X..XX .XXX ..X.. .XXX. .X... ..XXX",
+            ),
+            (
+                false,
+                32,
+                4096,
+                "XXXXX XXXXXXXXXXX X.X. ..... XXX.X ..X.. X.XXX",
+            ),
+        ] {
+            let expected = expected.replace(' ', "");
+            assert_eq!(
+                pattern(&mode_message(compact, layers, data_words)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn compact_symbols_have_the_standard_core() {
+        // Bullseye, orientation marks and ZXing's mode message for 2 layers
+        // and 29 data words, read clockwise from the top left.
+        let mode = mode_message(true, 2, 29);
+        let modules = draw(&Bits(vec![false; layer_bits(2, true)]), &mode, 2, true);
+        let expected = [
+            "...................",
+            "...................",
+            "...................",
+            "...................",
+            "....XX.X.XXX..X....",
+            "....XXXXXXXXXXX....",
+            "....XX.......X.....",
+            ".....X.XXXXX.X.....",
+            "....XX.X...X.X.....",
+            "....XX.X.X.X.X.....",
+            ".....X.X...X.XX....",
+            ".....X.XXXXX.XX....",
+            "....XX.......XX....",
+            ".....XXXXXXXXXX....",
+            "......X.X..........",
+            "...................",
+            "...................",
+            "...................",
+            "...................",
+        ];
+        let rows: Vec<String> = (0..modules.height)
+            .map(|y| {
+                (0..modules.width)
+                    .map(|x| if modules.is_dark(x, y) { 'X' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(rows, expected);
+
+        // The message runs down the left of the outer layer two modules at a
+        // time: two bits of padding, then binary shift (11111) stuffed to
+        // 111110 in six bit words.
+        let modules = encode("AB12").unwrap();
+        let left: Vec<(bool, bool)> = (0..4)
+            .map(|y| (modules.is_dark(0, y), modules.is_dark(1, y)))
+            .collect();
+        assert_eq!(
+            left,
+            [(false, false), (true, true), (true, true), (true, false)]
+        );
+    }
+
+    #[test]
+    fn symbols_grow_with_the_ticket() {
+        let stuffed = stuff(&Bits(vec![false; 6]), 6);
+        assert_eq!(stuffed.words(6), [0b000001, 0b011111]);
+
+        assert_eq!(encode("AB12").unwrap().width, 15);
+        assert_eq!(encode("AB12CD34").unwrap().width, 19);
+        // Six full size layers, plus a line of the reference grid each side.
+        assert_eq!(encode(&"A".repeat(100)).unwrap().width, 41);
+
+        let modules = encode("AB12").unwrap();
+        assert!(modules.is_dark(7, 7));
+        assert!(!modules.is_dark(8, 7));
+        assert!(modules.is_dark(9, 7));
+    }
+
+    #[test]
+    fn the_largest_symbol_holds_max_bytes_of_anything() {
+        let max_bytes = max_bytes();
+        // Zero bytes need the most stuffing.
+        encode(&"\0".repeat(max_bytes)).unwrap();
+        encode(&"\u{ff}".repeat(max_bytes / 2)).unwrap();
+        assert!(encode(&"\0".repeat(max_bytes + 16)).is_err());
+    }
+}
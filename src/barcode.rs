@@ -0,0 +1,827 @@
+//! Barcodes of every ticket, as one image file each in a directory or a ZIP
+//! archive.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use qrcode::{Color, EcLevel, QrCode};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{aztec, code128, columns::serial, datamatrix, pdf417};
+
+/// The kind of barcode drawn for each ticket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Symbology {
+    #[default]
+    Qr,
+    /// A linear barcode of printable ASCII, for handheld laser scanners.
+    Code128,
+    DataMatrix,
+    Aztec,
+    /// A stacked linear barcode, for the line scanners at gates and on
+    /// boarding passes.
+    Pdf417,
+}
+
+impl Symbology {
+    pub const ALL: [Symbology; 5] = [
+        Symbology::Qr,
+        Symbology::Code128,
+        Symbology::DataMatrix,
+        Symbology::Aztec,
+        Symbology::Pdf417,
+    ];
+
+    /// The narrowest quiet zone scanners expect, in modules.
+    pub fn quiet_zone(self) -> u32 {
+        match self {
+            Symbology::Qr => 4,
+            Symbology::Code128 => 10,
+            Symbology::DataMatrix => 1,
+            // The bullseye is found from the inside, so none is needed.
+            Symbology::Aztec => 0,
+            Symbology::Pdf417 => 2,
+        }
+    }
+
+    /// Checks that every ticket made of `chars`, `length` characters long,
+    /// fits in this symbology. DataMatrix, Aztec and PDF417 tickets must be
+    /// ASCII: their bytes are written as they are, without an ECI saying
+    /// they're UTF-8, and scanners would read them in their default code
+    /// page instead.
+    pub(crate) fn check(
+        self,
+        chars: &[char],
+        length: usize,
+        error_correction: QrErrorCorrection,
+    ) -> Result<(), String> {
+        if self == Symbology::Code128 {
+            let unencodable: String = chars.iter().filter(|&&c| !code128::can_encode(c)).collect();
+            if !unencodable.is_empty() {
+                return Err(format!(
+                    "Code 128 can only hold printable ASCII characters, but tickets may contain {unencodable:?}"
+                ));
+            }
+        }
+
+        if matches!(
+            self,
+            Symbology::DataMatrix | Symbology::Aztec | Symbology::Pdf417
+        ) {
+            let non_ascii: String = chars.iter().filter(|c| !c.is_ascii()).collect();
+            if !non_ascii.is_empty() {
+                return Err(format!(
+                    "{self} can only hold ASCII characters that scanners read back the same, but tickets may contain {non_ascii:?}"
+                ));
+            }
+        }
+
+        let char_bytes = chars.iter().map(|c| c.len_utf8()).max().unwrap_or(1);
+        let bytes = length * char_bytes;
+        let capacity = match self {
+            Symbology::Qr => error_correction.capacity(),
+            Symbology::Code128 => code128::MAX_LENGTH,
+            Symbology::DataMatrix => datamatrix::MAX_DATA_WORDS,
+            Symbology::Aztec => aztec::max_bytes(),
+            Symbology::Pdf417 => pdf417::max_bytes(chars),
+        };
+        if bytes > capacity {
+            return Err(format!(
+                "Tickets take up to {bytes} bytes, but the largest {self} holds {capacity}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Draws `ticket`.
+    fn encode(self, ticket: &str, error_correction: QrErrorCorrection) -> Result<Modules, String> {
+        match self {
+            Symbology::Qr => {
+                let code = QrCode::with_error_correction_level(ticket, error_correction.ec_level())
+                    .map_err(|err| format!("Failed to make a QR code of {ticket}: {err}"))?;
+                let width = code.width();
+                let mut modules = Modules::new(width, width);
+                for (i, color) in code.into_colors().into_iter().enumerate() {
+                    if color == Color::Dark {
+                        modules.set(i % width, i / width);
+                    }
+                }
+                Ok(modules)
+            }
+            Symbology::Code128 => code128::encode(ticket),
+            Symbology::DataMatrix => datamatrix::encode(ticket),
+            Symbology::Aztec => aztec::encode(ticket),
+            Symbology::Pdf417 => pdf417::encode(ticket),
+        }
+    }
+}
+
+impl fmt::Display for Symbology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Symbology::Qr => "QR code",
+            Symbology::Code128 => "Code 128",
+            Symbology::DataMatrix => "DataMatrix",
+            Symbology::Aztec => "Aztec code",
+            Symbology::Pdf417 => "PDF417",
+        })
+    }
+}
+
+impl FromStr for Symbology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "qr" => Ok(Symbology::Qr),
+            "code128" => Ok(Symbology::Code128),
+            "datamatrix" => Ok(Symbology::DataMatrix),
+            "aztec" => Ok(Symbology::Aztec),
+            "pdf417" => Ok(Symbology::Pdf417),
+            _ => Err(format!(
+                "Unknown symbology {s}, expected qr, code128, datamatrix, aztec or pdf417"
+            )),
+        }
+    }
+}
+
+/// A drawn barcode, `width` by `height` modules. Linear codes are drawn as
+/// bars of equal height.
+pub(crate) struct Modules {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    dark: Vec<bool>,
+}
+
+impl Modules {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            dark: vec![false; width * height],
+        }
+    }
+
+    /// Darkens the module `x` from the left and `y` from the top.
+    pub(crate) fn set(&mut self, x: usize, y: usize) {
+        self.dark[y * self.width + x] = true;
+    }
+
+    /// Darkens column `x` from top to bottom.
+    pub(crate) fn set_column(&mut self, x: usize) {
+        for y in 0..self.height {
+            self.set(x, y);
+        }
+    }
+
+    pub(crate) fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.dark[y * self.width + x]
+    }
+
+    /// Whether the module at `(x, y)`, counting from the outside of a
+    /// `quiet_zone` modules wide border, is dark.
+    fn is_dark_padded(&self, quiet_zone: usize, x: usize, y: usize) -> bool {
+        let (Some(x), Some(y)) = (x.checked_sub(quiet_zone), y.checked_sub(quiet_zone)) else {
+            return false;
+        };
+
+        x < self.width && y < self.height && self.is_dark(x, y)
+    }
+}
+
+/// How much of a QR code can be damaged before it stops scanning. Higher
+/// levels make bigger codes. Other symbologies have a fixed level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QrErrorCorrection {
+    /// About 7% can be restored.
+    Low,
+    /// About 15% can be restored.
+    #[default]
+    Medium,
+    /// About 25% can be restored.
+    Quartile,
+    /// About 30% can be restored.
+    High,
+}
+
+impl QrErrorCorrection {
+    pub const ALL: [QrErrorCorrection; 4] = [
+        QrErrorCorrection::Low,
+        QrErrorCorrection::Medium,
+        QrErrorCorrection::Quartile,
+        QrErrorCorrection::High,
+    ];
+
+    fn ec_level(self) -> EcLevel {
+        match self {
+            QrErrorCorrection::Low => EcLevel::L,
+            QrErrorCorrection::Medium => EcLevel::M,
+            QrErrorCorrection::Quartile => EcLevel::Q,
+            QrErrorCorrection::High => EcLevel::H,
+        }
+    }
+
+    /// The most bytes the largest QR code holds at this level.
+    fn capacity(self) -> usize {
+        match self {
+            QrErrorCorrection::Low => 2953,
+            QrErrorCorrection::Medium => 2331,
+            QrErrorCorrection::Quartile => 1663,
+            QrErrorCorrection::High => 1273,
+        }
+    }
+}
+
+impl fmt::Display for QrErrorCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QrErrorCorrection::Low => "Low (7%)",
+            QrErrorCorrection::Medium => "Medium (15%)",
+            QrErrorCorrection::Quartile => "Quartile (25%)",
+            QrErrorCorrection::High => "High (30%)",
+        })
+    }
+}
+
+impl FromStr for QrErrorCorrection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "l" | "low" => Ok(QrErrorCorrection::Low),
+            "m" | "medium" => Ok(QrErrorCorrection::Medium),
+            "q" | "quartile" => Ok(QrErrorCorrection::Quartile),
+            "h" | "high" => Ok(QrErrorCorrection::High),
+            _ => Err(format!(
+                "Unknown error correction level {s}, expected l, m, q or h"
+            )),
+        }
+    }
+}
+
+/// The image format barcodes are saved in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BarcodeImageFormat {
+    /// Black and white PNG, `module_size` pixels per module.
+    #[default]
+    Png,
+    /// SVG with a `module_size` pixel module at its natural size, which
+    /// scales without blurring.
+    Svg,
+}
+
+impl BarcodeImageFormat {
+    pub const ALL: [BarcodeImageFormat; 2] = [BarcodeImageFormat::Png, BarcodeImageFormat::Svg];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BarcodeImageFormat::Png => "png",
+            BarcodeImageFormat::Svg => "svg",
+        }
+    }
+}
+
+impl fmt::Display for BarcodeImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BarcodeImageFormat::Png => "PNG",
+            BarcodeImageFormat::Svg => "SVG",
+        })
+    }
+}
+
+impl FromStr for BarcodeImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(BarcodeImageFormat::Png),
+            "svg" => Ok(BarcodeImageFormat::Svg),
+            _ => Err(format!("Unknown image format {s}, expected png or svg")),
+        }
+    }
+}
+
+/// What each barcode's file is named after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BarcodeFileNames {
+    /// The ticket itself. Characters other than ASCII letters, digits, `-`
+    /// and `_` are percent-encoded, so `AB/12` is saved as `AB%2F12.png`.
+    #[default]
+    Ticket,
+    /// The ticket's serial number, counting up from the start of the
+    /// [`Column::Serial`](crate::Column) column, or from 1 without one.
+    Serial,
+}
+
+impl BarcodeFileNames {
+    pub const ALL: [BarcodeFileNames; 2] = [BarcodeFileNames::Ticket, BarcodeFileNames::Serial];
+}
+
+impl fmt::Display for BarcodeFileNames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BarcodeFileNames::Ticket => "Ticket",
+            BarcodeFileNames::Serial => "Serial number",
+        })
+    }
+}
+
+impl FromStr for BarcodeFileNames {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ticket" => Ok(BarcodeFileNames::Ticket),
+            "serial" => Ok(BarcodeFileNames::Serial),
+            _ => Err(format!(
+                "Unknown file naming {s}, expected ticket or serial"
+            )),
+        }
+    }
+}
+
+/// Barcodes of every ticket, written in the same pass as the batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BarcodeExport {
+    /// A directory to put one image per ticket in, or a file ending in
+    /// `.zip` to put them all in one archive. Images already in the
+    /// directory are never replaced.
+    pub path: String,
+    pub symbology: Symbology,
+    pub image_format: BarcodeImageFormat,
+    /// Only used by QR codes.
+    pub error_correction: QrErrorCorrection,
+    /// Pixels per module, the narrowest bar or smallest square of the code.
+    pub module_size: u32,
+    /// Width of the blank border in modules. [`TicketSpec::validate`]
+    /// rejects anything narrower than [`Symbology::quiet_zone`].
+    ///
+    /// [`TicketSpec::validate`]: crate::TicketSpec::validate
+    pub quiet_zone: u32,
+    pub file_names: BarcodeFileNames,
+}
+
+impl Default for BarcodeExport {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            symbology: Symbology::default(),
+            image_format: BarcodeImageFormat::default(),
+            error_correction: QrErrorCorrection::default(),
+            module_size: 8,
+            quiet_zone: Symbology::default().quiet_zone(),
+            file_names: BarcodeFileNames::default(),
+        }
+    }
+}
+
+impl BarcodeExport {
+    /// Whether the barcodes go into a ZIP archive rather than a directory.
+    pub fn is_zip(&self) -> bool {
+        Path::new(&self.path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+    }
+}
+
+/// Where the images go.
+enum Sink {
+    /// Written to `part_path`, a directory next to `path`, and moved into
+    /// `path` once the batch is saved. `written` lists the file names saved
+    /// so far.
+    Directory {
+        path: PathBuf,
+        part_path: PathBuf,
+        written: Vec<String>,
+    },
+    /// Written next to the archive's path and moved there once complete.
+    Zip {
+        archive: Box<ZipWriter<File>>,
+        part_path: String,
+    },
+    /// A complete archive waiting to be moved into place.
+    Finished { part_path: String },
+}
+
+/// Renders the barcodes of a batch. Anything written is removed again if it
+/// is dropped before [`BarcodeWriter::commit`].
+pub(crate) struct BarcodeWriter<'a> {
+    export: &'a BarcodeExport,
+    serial_start: u64,
+    sink: Option<Sink>,
+    image: Vec<u8>,
+}
+
+impl<'a> BarcodeWriter<'a> {
+    pub(crate) fn new(export: &'a BarcodeExport, serial_start: u64) -> Result<Self, String> {
+        let sink = if export.is_zip() {
+            let part_path = format!("{}.part", export.path);
+            let file = File::create(&part_path)
+                .map_err(|err| format!("Failed to create file {part_path}: {err}"))?;
+            Sink::Zip {
+                archive: Box::new(ZipWriter::new(file)),
+                part_path,
+            }
+        } else {
+            // Without any trailing separator, so `.part` isn't put inside.
+            let path = Path::new(&export.path).components().as_path().to_owned();
+            let mut part_path = path.clone().into_os_string();
+            part_path.push(".part");
+            let part_path = PathBuf::from(part_path);
+            fs::create_dir_all(&part_path).map_err(|err| {
+                format!("Failed to create directory {}: {err}", part_path.display())
+            })?;
+            Sink::Directory {
+                path,
+                part_path,
+                written: Vec::new(),
+            }
+        };
+
+        Ok(Self {
+            export,
+            serial_start,
+            sink: Some(sink),
+            image: Vec::new(),
+        })
+    }
+
+    /// Saves the barcode of `ticket`, the `index`th ticket of the batch.
+    pub(crate) fn write(&mut self, ticket: &str, index: usize) -> Result<(), String> {
+        let modules = self
+            .export
+            .symbology
+            .encode(ticket, self.export.error_correction)?;
+        self.image.clear();
+        match self.export.image_format {
+            BarcodeImageFormat::Png => render_png(&modules, self.export, &mut self.image)?,
+            BarcodeImageFormat::Svg => render_svg(&modules, self.export, &mut self.image),
+        }
+
+        let file_name = match self.export.file_names {
+            BarcodeFileNames::Ticket => percent_encode(ticket),
+            BarcodeFileNames::Serial => serial(self.serial_start, index)?.to_string(),
+        };
+        let file_name = format!("{file_name}.{}", self.export.image_format.extension());
+
+        match self.sink.as_mut().expect("only taken when committed") {
+            Sink::Directory {
+                path,
+                part_path,
+                written,
+            } => {
+                // Checked up front so a long job doesn't fail at the end.
+                let target = path.join(&file_name);
+                if target.exists() {
+                    return Err(format!("Barcode {} already exists", target.display()));
+                }
+                let file_path = part_path.join(&file_name);
+                fs::write(&file_path, &self.image).map_err(|err| {
+                    format!("Failed to write barcode {}: {err}", file_path.display())
+                })?;
+                written.push(file_name);
+            }
+            Sink::Zip { archive, .. } => {
+                // PNGs are compressed already.
+                let compression = match self.export.image_format {
+                    BarcodeImageFormat::Png => CompressionMethod::Stored,
+                    BarcodeImageFormat::Svg => CompressionMethod::Deflated,
+                };
+                let options = SimpleFileOptions::default().compression_method(compression);
+                archive
+                    .start_file(file_name, options)
+                    .map_err(|err| format!("Failed to write barcode archive: {err}"))?;
+                archive
+                    .write_all(&self.image)
+                    .map_err(|err| format!("Failed to write barcode archive: {err}"))?;
+            }
+            Sink::Finished { .. } => unreachable!("no barcodes are written once finished"),
+        }
+
+        Ok(())
+    }
+
+    /// Completes the archive, if there is one, without moving it into place
+    /// yet.
+    pub(crate) fn finish(&mut self) -> Result<(), String> {
+        match self.sink.take() {
+            Some(Sink::Zip { archive, part_path }) => {
+                let result = archive
+                    .finish()
+                    .map_err(|err| err.to_string())
+                    .and_then(|mut file| file.flush().map_err(|err| err.to_string()));
+                // Put back either way, so a failed archive is still removed.
+                self.sink = Some(Sink::Finished { part_path });
+                result.map_err(|err| format!("Failed to write barcode archive: {err}"))
+            }
+            sink => {
+                self.sink = sink;
+                Ok(())
+            }
+        }
+    }
+
+    /// Moves the finished archive or the images into place. Images are only
+    /// moved into a directory that already exists when none of them would
+    /// replace a file there.
+    pub(crate) fn commit(mut self) -> Result<(), String> {
+        match self.sink.take() {
+            Some(Sink::Finished { part_path }) => {
+                let result = fs::rename(&part_path, &self.export.path).map_err(|err| {
+                    format!("Failed to move {part_path} to {}: {err}", self.export.path)
+                });
+                if result.is_err() {
+                    let _ = fs::remove_file(&part_path);
+                }
+                result
+            }
+            Some(Sink::Zip { .. }) => {
+                unreachable!("archives are finished before they're committed")
+            }
+            Some(Sink::Directory {
+                path,
+                part_path,
+                written,
+            }) => {
+                let result = commit_directory(&path, &part_path, &written);
+                if result.is_err() {
+                    // Whatever is left is removed on drop.
+                    self.sink = Some(Sink::Directory {
+                        path,
+                        part_path,
+                        written,
+                    });
+                }
+                result
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Moves the `written` images from `part_path` into `path`, in one rename if
+/// `path` doesn't exist yet. If any can't be moved, those that were are
+/// removed again.
+fn commit_directory(path: &Path, part_path: &Path, written: &[String]) -> Result<(), String> {
+    if !path.exists() {
+        return fs::rename(part_path, path).map_err(|err| {
+            format!(
+                "Failed to move {} to {}: {err}",
+                part_path.display(),
+                path.display()
+            )
+        });
+    }
+
+    let mut moved = Vec::new();
+    for file_name in written {
+        let (from, to) = (part_path.join(file_name), path.join(file_name));
+        let result = if to.exists() {
+            Err(format!("Barcode {} already exists", to.display()))
+        } else {
+            fs::rename(&from, &to).map_err(|err| {
+                format!(
+                    "Failed to move {} to {}: {err}",
+                    from.display(),
+                    to.display()
+                )
+            })
+        };
+        if let Err(err) = result {
+            for file_path in moved {
+                let _ = fs::remove_file(file_path);
+            }
+            return Err(err);
+        }
+        moved.push(to);
+    }
+    let _ = fs::remove_dir(part_path);
+
+    Ok(())
+}
+
+impl Drop for BarcodeWriter<'_> {
+    fn drop(&mut self) {
+        match self.sink.take() {
+            Some(Sink::Directory {
+                part_path, written, ..
+            }) => {
+                for file_name in written {
+                    let _ = fs::remove_file(part_path.join(file_name));
+                }
+                let _ = fs::remove_dir(part_path);
+            }
+            Some(Sink::Zip { archive, part_path }) => {
+                drop(archive);
+                let _ = fs::remove_file(part_path);
+            }
+            Some(Sink::Finished { part_path }) => {
+                let _ = fs::remove_file(part_path);
+            }
+            None => {}
+        }
+    }
+}
+
+/// Appends `modules` to `buf` as a 1-bit grayscale PNG.
+fn render_png(modules: &Modules, export: &BarcodeExport, buf: &mut Vec<u8>) -> Result<(), String> {
+    let module_size = export.module_size as usize;
+    let quiet_zone = export.quiet_zone as usize;
+    let (columns, rows) = (
+        modules.width + 2 * quiet_zone,
+        modules.height + 2 * quiet_zone,
+    );
+    let (width, height) = (columns * module_size, rows * module_size);
+    let row_bytes = width.div_ceil(8);
+
+    // Rows of set bits are white, and dark modules clear theirs.
+    let mut data = vec![0xFF; row_bytes * height];
+    for y in 0..rows {
+        for x in 0..columns {
+            if !modules.is_dark_padded(quiet_zone, x, y) {
+                continue;
+            }
+            for row in y * module_size..(y + 1) * module_size {
+                for column in x * module_size..(x + 1) * module_size {
+                    data[row * row_bytes + column / 8] &= !(0x80 >> (column % 8));
+                }
+            }
+        }
+    }
+
+    let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+        return Err("Barcode image is too large".to_owned());
+    };
+    let mut encoder = png::Encoder::new(&mut *buf, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(&data)?;
+            writer.finish()
+        })
+        .map_err(|err| format!("Failed to encode barcode: {err}"))
+}
+
+/// Appends `modules` to `buf` as an SVG with one unit per module.
+fn render_svg(modules: &Modules, export: &BarcodeExport, buf: &mut Vec<u8>) {
+    let quiet_zone = export.quiet_zone as usize;
+    let (columns, rows) = (
+        modules.width + 2 * quiet_zone,
+        modules.height + 2 * quiet_zone,
+    );
+    let module_size = export.module_size as usize;
+    let (width, height) = (columns * module_size, rows * module_size);
+
+    let mut svg = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {columns} {rows}\" shape-rendering=\"crispEdges\">\n\
+         <rect width=\"{columns}\" height=\"{rows}\" fill=\"#fff\"/>\n\
+         <path fill=\"#000\" d=\""
+    );
+    for y in 0..rows {
+        for x in 0..columns {
+            if modules.is_dark_padded(quiet_zone, x, y) {
+                svg.push_str(&format!("M{x} {y}h1v1h-1z"));
+            }
+        }
+    }
+    svg.push_str("\"/>\n</svg>\n");
+
+    buf.extend_from_slice(svg.as_bytes());
+}
+
+/// Names Windows keeps for devices, whatever extension follows them.
+const WINDOWS_DEVICE_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Escapes every byte of `ticket` that isn't an ASCII letter, digit, `-` or
+/// `_`, so each ticket gets a distinct name that's valid on any system.
+/// Tickets that spell a Windows device name, like `CON`, have their first
+/// letter escaped too.
+fn percent_encode(ticket: &str) -> String {
+    let is_device = WINDOWS_DEVICE_NAMES
+        .iter()
+        .any(|device| device.eq_ignore_ascii_case(ticket));
+
+    let mut name = String::with_capacity(ticket.len());
+    for (i, byte) in ticket.bytes().enumerate() {
+        if (byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_') && !(is_device && i == 0)
+        {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_are_escaped() {
+        assert_eq!(percent_encode("AB-12_cd"), "AB-12_cd");
+        assert_eq!(percent_encode("A/B.C%"), "A%2FB%2EC%25");
+        assert_eq!(percent_encode("CON"), "%43ON");
+        assert_eq!(percent_encode("nul"), "%6Eul");
+        assert_eq!(percent_encode("COM1"), "%43OM1");
+        assert_eq!(percent_encode("LPT9"), "%4CPT9");
+        assert_eq!(percent_encode("CONS"), "CONS");
+        assert_eq!(percent_encode("COM0"), "COM0");
+    }
+
+    #[test]
+    fn images_have_the_requested_size() {
+        let modules = Symbology::Qr
+            .encode("AB12", QrErrorCorrection::Medium)
+            .unwrap();
+        let export = BarcodeExport {
+            module_size: 3,
+            quiet_zone: 2,
+            ..Default::default()
+        };
+        // Version 1 codes are 21 modules wide.
+        let pixels = (21 + 2 * 2) * 3;
+
+        let mut png = Vec::new();
+        render_png(&modules, &export, &mut png).unwrap();
+        let decoder = png::Decoder::new(std::io::Cursor::new(png));
+        let info = decoder.read_info().unwrap().info().clone();
+        assert_eq!((info.width, info.height), (pixels, pixels));
+
+        let mut svg = Vec::new();
+        render_svg(&modules, &export, &mut svg);
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains(&format!("width=\"{pixels}\"")));
+        assert!(svg.contains("viewBox=\"0 0 25 25\""));
+    }
+
+    #[test]
+    fn symbologies_check_what_tickets_hold() {
+        let chars: Vec<char> = "ABC123".chars().collect();
+        for symbology in Symbology::ALL {
+            symbology
+                .check(&chars, 12, QrErrorCorrection::High)
+                .unwrap();
+            let modules = symbology.encode("ABC123321CBA", QrErrorCorrection::High);
+            assert!(modules.is_ok(), "{symbology}");
+        }
+
+        let accented = ['A', '\u{e9}'];
+        assert!(
+            Symbology::Code128
+                .check(&accented, 8, QrErrorCorrection::Medium)
+                .is_err()
+        );
+        Symbology::Qr
+            .check(&accented, 8, QrErrorCorrection::Medium)
+            .unwrap();
+        for symbology in [Symbology::DataMatrix, Symbology::Aztec, Symbology::Pdf417] {
+            let error = symbology
+                .check(&accented, 8, QrErrorCorrection::Medium)
+                .unwrap_err();
+            assert!(error.contains("ASCII"), "{error}");
+        }
+
+        Symbology::Code128
+            .check(&chars, code128::MAX_LENGTH, QrErrorCorrection::Medium)
+            .unwrap();
+        assert!(
+            Symbology::Code128
+                .check(&chars, code128::MAX_LENGTH + 1, QrErrorCorrection::Medium)
+                .is_err()
+        );
+
+        // One byte per character, up to what the largest symbol holds.
+        let ascii = ['A', '\u{7f}'];
+        for (symbology, capacity) in [
+            (Symbology::DataMatrix, datamatrix::MAX_DATA_WORDS),
+            (Symbology::Aztec, aztec::max_bytes()),
+            (Symbology::Pdf417, pdf417::max_bytes(&ascii)),
+        ] {
+            symbology
+                .check(&ascii, capacity, QrErrorCorrection::Medium)
+                .unwrap();
+            assert!(
+                symbology
+                    .check(&ascii, capacity + 1, QrErrorCorrection::Medium)
+                    .is_err(),
+                "{symbology}"
+            );
+        }
+    }
+}
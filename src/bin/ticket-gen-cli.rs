@@ -0,0 +1,331 @@
+//! Headless front end for generating ticket batches from scripts or on
+//! machines without a display.
+
+use std::{
+    collections::BTreeMap,
+    io::{IsTerminal, Write},
+    process::ExitCode,
+    sync::atomic::AtomicBool,
+};
+
+use clap::Parser;
+use ticket_gen::{
+    BarcodeExport, BarcodeFileNames, BarcodeImageFormat, CheckAlgorithm, ClassLimits, Column,
+    CustomClass, GenerationMode, HashAlgorithm, HashExport, OutputFormat, QrErrorCorrection,
+    Symbology, TicketSpec, build_batch_with_progress, load_issued_tickets, load_wordlist,
+};
+
+/// Generate a batch of unique random tickets and write them to a file.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Include capital letters (A-Z)
+    #[arg(short = 'C', long)]
+    capitals: bool,
+
+    /// Include lowercase letters (a-z)
+    #[arg(short = 'L', long)]
+    lowercase: bool,
+
+    /// Include numbers (0-9)
+    #[arg(short = 'N', long)]
+    numbers: bool,
+
+    /// Include specials (,.;:"'!%#)
+    #[arg(short = 'S', long)]
+    specials: bool,
+
+    /// Add a custom character class, e.g. --class hex=0123456789ABCDEF.
+    /// May be repeated
+    #[arg(long = "class", value_name = "NAME=CHARS", value_parser = parse_custom_class)]
+    custom_classes: Vec<CustomClass>,
+
+    /// Require at least N characters from a class, e.g. --min "Numbers=2".
+    /// May be repeated
+    #[arg(long, value_name = "CLASS=N", value_parser = parse_class_count)]
+    min: Vec<(String, usize)>,
+
+    /// Allow at most N characters from a class, e.g. --max "Specials=1".
+    /// May be repeated
+    #[arg(long, value_name = "CLASS=N", value_parser = parse_class_count)]
+    max: Vec<(String, usize)>,
+
+    /// Characters to leave out of the character set
+    #[arg(short = 'x', long, default_value = "")]
+    exclude: String,
+
+    /// Reject tickets containing offensive words from the built-in list
+    #[arg(short = 'b', long)]
+    blocklist: bool,
+
+    /// Reject tickets containing any word from this file, one per line.
+    /// May be repeated
+    #[arg(long, value_name = "FILE")]
+    blocklist_file: Vec<String>,
+
+    /// Redraw tickets a spreadsheet would read as a formula, number, date,
+    /// boolean or error, like =A1, -12, 000123, 1E10, 12/31, Mar-1 or #N/A.
+    /// Column values like that are refused
+    #[arg(long)]
+    spreadsheet_safe: bool,
+
+    /// Drop look-alike characters such as 0/O and 1/l/I
+    #[arg(short = 'H', long)]
+    human_friendly: bool,
+
+    /// Number of tickets to generate
+    #[arg(short = 'n', long)]
+    count: usize,
+
+    /// Number of characters in each ticket
+    #[arg(short = 'l', long, required_unless_present = "template")]
+    length: Option<usize>,
+
+    /// Pattern for each ticket, e.g. "EVT-####-AAAA" or "[A-Z]{2}[0-9]{6}"
+    #[arg(short, long)]
+    template: Option<String>,
+
+    /// Make every pair of tickets differ in at least this many positions
+    /// (Hamming distance)
+    #[arg(short = 'd', long, default_value_t = 0)]
+    min_distance: usize,
+
+    /// Append check characters: one with luhn, damm, verhoeff or iso7064,
+    /// two with mod97-10, mod661-26 or mod1271-36
+    #[arg(short = 'k', long)]
+    check: Option<CheckAlgorithm>,
+
+    /// Seed for reproducible batches. A random seed is used and printed
+    /// when omitted
+    #[arg(short, long)]
+    seed: Option<u64>,
+
+    /// Decode tickets from a permutation of the keyspace keyed by the seed,
+    /// so they are unique without being kept in memory
+    #[arg(short, long)]
+    permuted: bool,
+
+    /// First permuted index to decode. Pass the end of the previous batch's
+    /// index range to continue it
+    #[arg(long, default_value_t = 0, requires = "permuted")]
+    start_index: u128,
+
+    /// Draw tickets on this many threads, at most 256, or one per core when
+    /// 0. A seed gives the same batch for any thread count, but not the same
+    /// batch as running without this flag
+    #[arg(short = 'j', long, conflicts_with = "permuted")]
+    threads: Option<usize>,
+
+    /// Never repeat a ticket from this earlier batch, a CSV file or a .txt
+    /// file with one ticket per line. May be repeated
+    #[arg(long, value_name = "FILE")]
+    previous: Vec<String>,
+
+    /// Text file of every ticket issued so far. Its tickets are never
+    /// repeated, and the new batch is added to it
+    #[arg(long, value_name = "FILE")]
+    registry: Option<String>,
+
+    /// Start the file with a row of column names
+    #[arg(long)]
+    header: bool,
+
+    /// Add a column: ticket, serial[=START], batch-id=ID, created-at,
+    /// ticket-type=TYPE or HEADER=VALUE for a constant. May be repeated,
+    /// and tickets come first unless the ticket column is listed
+    #[arg(short = 'c', long = "column", value_name = "COLUMN")]
+    columns: Vec<Column>,
+
+    /// File format: csv, json, jsonl, txt, sql or xlsx. Guessed from the output
+    /// file's extension when omitted, falling back to csv
+    #[arg(short, long)]
+    format: Option<OutputFormat>,
+
+    /// Table the sql format inserts into
+    #[arg(long, default_value = "tickets")]
+    table: String,
+
+    /// Also write a salted hash of every ticket to this file, in the same
+    /// format, for a server to check tickets against
+    #[arg(long, value_name = "FILE")]
+    hashes: Option<String>,
+
+    /// How tickets are hashed: sha256, or argon2id which is much slower
+    #[arg(long, default_value = "sha256", requires = "hashes")]
+    hash_algorithm: HashAlgorithm,
+
+    /// Keep each ticket next to its hash instead of leaving it out
+    #[arg(long, requires = "hashes")]
+    hash_with_tickets: bool,
+
+    /// Also save a barcode of every ticket into this directory, or into one
+    /// archive if the path ends in .zip
+    #[arg(long, value_name = "PATH")]
+    barcodes: Option<String>,
+
+    /// Kind of barcode: qr, code128, datamatrix, aztec or pdf417
+    #[arg(long, default_value = "qr", requires = "barcodes")]
+    symbology: Symbology,
+
+    /// Image format of the barcodes: png or svg
+    #[arg(long, default_value = "png", requires = "barcodes")]
+    barcode_format: BarcodeImageFormat,
+
+    /// QR error correction level: l, m, q or h
+    #[arg(long, default_value = "m", requires = "barcodes")]
+    qr_error_correction: QrErrorCorrection,
+
+    /// Pixels per barcode module
+    #[arg(long, default_value_t = 8, requires = "barcodes")]
+    module_size: u32,
+
+    /// Blank border around each barcode, in modules, at least what the
+    /// symbology needs [default: what the symbology needs]
+    #[arg(long, requires = "barcodes")]
+    quiet_zone: Option<u32>,
+
+    /// Name barcode files after the ticket or its serial number
+    #[arg(long, default_value = "ticket", requires = "barcodes")]
+    barcode_names: BarcodeFileNames,
+
+    /// Destination file
+    #[arg(short, long)]
+    output: String,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mut class_limits: BTreeMap<String, ClassLimits> = BTreeMap::new();
+    for (name, min) in args.min {
+        class_limits.entry(name).or_default().min = min;
+    }
+    for (name, max) in args.max {
+        class_limits.entry(name).or_default().max = Some(max);
+    }
+
+    let mut blocked_words = Vec::new();
+    for file_path in &args.blocklist_file {
+        match load_wordlist(file_path) {
+            Ok(words) => blocked_words.extend(words),
+            Err(err) => {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut issued_tickets = Vec::new();
+    for file_path in &args.previous {
+        match load_issued_tickets(file_path) {
+            Ok(tickets) => issued_tickets.extend(tickets),
+            Err(err) => {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let format = match args
+        .format
+        .or_else(|| OutputFormat::from_path(&args.output))
+        .unwrap_or_default()
+    {
+        OutputFormat::Sql { .. } => OutputFormat::Sql { table: args.table },
+        format => format,
+    };
+
+    let spec = TicketSpec {
+        capital_letters: args.capitals,
+        lowercase_letters: args.lowercase,
+        numbers: args.numbers,
+        specials: args.specials,
+        custom_classes: args.custom_classes,
+        class_limits,
+        rejected_chars: args.exclude,
+        human_friendly: args.human_friendly,
+        ticket_length: args.length.unwrap_or_default(),
+        ticket_count: args.count,
+        template: args.template.unwrap_or_default(),
+        check_algorithm: args.check,
+        seed: args.seed,
+        builtin_blocklist: args.blocklist,
+        blocked_words,
+        spreadsheet_safe: args.spreadsheet_safe,
+        min_distance: args.min_distance,
+        mode: match (args.permuted, args.threads) {
+            (true, _) => GenerationMode::Permuted {
+                start: args.start_index,
+            },
+            (false, Some(threads)) => GenerationMode::Sharded { threads },
+            (false, None) => GenerationMode::Random,
+        },
+        issued_tickets,
+        registry: args.registry,
+        header: args.header,
+        columns: args.columns,
+        format,
+        hashes: args.hashes.map(|path| HashExport {
+            path,
+            algorithm: args.hash_algorithm,
+            with_tickets: args.hash_with_tickets,
+        }),
+        barcodes: args.barcodes.map(|path| BarcodeExport {
+            path,
+            symbology: args.symbology,
+            image_format: args.barcode_format,
+            error_correction: args.qr_error_correction,
+            module_size: args.module_size,
+            quiet_zone: args.quiet_zone.unwrap_or(args.symbology.quiet_zone()),
+            file_names: args.barcode_names,
+        }),
+    };
+
+    // Progress goes to stderr, and only when someone is watching it.
+    let mut stderr = std::io::stderr();
+    let show_progress = stderr.is_terminal();
+    let result =
+        build_batch_with_progress(&spec, &args.output, &AtomicBool::new(false), |progress| {
+            if show_progress {
+                let _ = write!(stderr, "\r\x1b[2K{progress}");
+                let _ = stderr.flush();
+            }
+        });
+    if show_progress {
+        eprint!("\r\x1b[2K");
+    }
+
+    match result {
+        Ok(summary) => {
+            println!("{summary}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_custom_class(arg: &str) -> Result<CustomClass, String> {
+    let (name, chars) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected NAME=CHARS, got {arg}"))?;
+
+    Ok(CustomClass {
+        name: name.to_owned(),
+        chars: chars.to_owned(),
+        enabled: true,
+    })
+}
+
+fn parse_class_count(arg: &str) -> Result<(String, usize), String> {
+    let (name, count) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected CLASS=N, got {arg}"))?;
+    let count = count
+        .parse()
+        .map_err(|err| format!("Invalid count for {name}: {err}"))?;
+
+    Ok((name.to_owned(), count))
+}
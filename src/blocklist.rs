@@ -0,0 +1,170 @@
+//! Rejects tickets that spell offensive words, including leetspeak
+//! spellings like `SH1T` or `F4G`.
+
+use std::collections::{BTreeSet, HashSet};
+
+use crate::spreadsheet::is_spreadsheet_safe;
+
+/// Offensive words in English, Spanish, French, German, Italian, Portuguese
+/// and Dutch that are short enough to turn up in random codes.
+pub const BUILTIN_BLOCKLIST: &[&str] = &[
+    // English
+    "anal", "anus", "arse", "ass", "bastard", "bitch", "boob", "chink", "cock", "cum", "cunt",
+    "dick", "dildo", "fag", "fuck", "homo", "jizz", "kike", "kkk", "nazi", "nigga", "nigger",
+    "paki", "penis", "piss", "porn", "puss", "rape", "retard", "sex", "shit", "slut", "spic",
+    "tit", "twat", "vagina", "wank", "whore", // Spanish
+    "cabron", "chinga", "coño", "culo", "joder", "marica", "mierda", "pendejo", "polla", "puta",
+    "puto", "verga", // French
+    "bite", "connard", "encule", "merde", "putain", "pute", "salope", // German
+    "arsch", "fick", "fotze", "hure", "nutte", "scheisse", "schwanz", "wichser",
+    // Italian
+    "cazzo", "figa", "merda", "minchia", "stronzo", "troia", // Portuguese
+    "buceta", "caralho", "foda", "porra", "viado", // Dutch
+    "hoer", "kanker", "klootzak", "kut", "lul",
+];
+
+/// Reads a wordlist with one word per line. Blank lines and lines starting
+/// with `#` are skipped.
+pub fn load_wordlist(file_path: &str) -> Result<Vec<String>, String> {
+    let contents = std::fs::read_to_string(file_path)
+        .map_err(|err| format!("Failed to read wordlist {file_path}: {err}"))?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Folded blocked words, grouped so a ticket only needs one lookup per
+/// position and word length.
+#[derive(Debug, Clone)]
+pub(crate) struct Blocklist {
+    words: HashSet<String>,
+    lengths: BTreeSet<usize>,
+    /// Also blocks tickets a spreadsheet would misread, see
+    /// [`TicketSpec::spreadsheet_safe`](crate::TicketSpec).
+    spreadsheet_safe: bool,
+}
+
+impl Blocklist {
+    pub(crate) fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut blocklist = Self {
+            words: HashSet::new(),
+            lengths: BTreeSet::new(),
+            spreadsheet_safe: false,
+        };
+        for word in words {
+            let folded: Vec<char> = word.chars().filter_map(|c| fold(c, true)).collect();
+            if folded.is_empty() {
+                continue;
+            }
+            blocklist.lengths.insert(folded.len());
+            blocklist.words.insert(folded.into_iter().collect());
+        }
+
+        blocklist
+    }
+
+    /// Blocks tickets a spreadsheet would misread as well.
+    pub(crate) fn spreadsheet_safe(self) -> Self {
+        Self {
+            spreadsheet_safe: true,
+            ..self
+        }
+    }
+
+    /// Whether any blocked word appears in `ticket` once both are folded, or
+    /// a spreadsheet would misread it in spreadsheet-safe mode. Digits only
+    /// stand in for letters in tickets that have a letter, so plain numbers
+    /// like `8008` pass. `folded` and `window` are scratch space, so checking
+    /// many tickets doesn't allocate for each.
+    pub(crate) fn is_blocked(
+        &self,
+        ticket: &str,
+        folded: &mut Vec<char>,
+        window: &mut String,
+    ) -> bool {
+        if self.spreadsheet_safe && !is_spreadsheet_safe(ticket) {
+            return true;
+        }
+
+        let digits_as_letters = ticket.chars().any(char::is_alphabetic);
+        folded.clear();
+        folded.extend(ticket.chars().filter_map(|c| fold(c, digits_as_letters)));
+        for start in 0..folded.len() {
+            for length in &self.lengths {
+                let Some(chars) = folded.get(start..start + length) else {
+                    break;
+                };
+                window.clear();
+                window.extend(chars);
+                if self.words.contains(window.as_str()) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// Maps look-alike characters onto the letter they stand in for and drops
+/// anything that isn't a letter, so `F.U.C.K` and `FU(K` fold like `fuck`.
+/// `i` and `l` fold together since `1` and `|` pass for either. Digits are
+/// dropped too unless `digits_as_letters` is set.
+fn fold(c: char, digits_as_letters: bool) -> Option<char> {
+    let c = c.to_lowercase().next()?;
+    match c {
+        c if c.is_ascii_digit() && !digits_as_letters => None,
+        '0' => Some('o'),
+        '1' | '!' | '|' | 'l' => Some('i'),
+        '2' => Some('z'),
+        '3' => Some('e'),
+        '4' | '@' => Some('a'),
+        '5' | '$' => Some('s'),
+        '6' | '9' => Some('g'),
+        '7' | '+' => Some('t'),
+        '8' => Some('b'),
+        '(' => Some('c'),
+        c if c.is_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(blocklist: &Blocklist, ticket: &str) -> bool {
+        blocklist.is_blocked(ticket, &mut Vec::new(), &mut String::new())
+    }
+
+    #[test]
+    fn catches_leetspeak_and_separators() {
+        let blocklist = Blocklist::new(["shit", "fag"]);
+        assert!(blocks(&blocklist, "X5H1TQ"));
+        assert!(blocks(&blocklist, "ab$h!t"));
+        assert!(blocks(&blocklist, "F.4.G9"));
+        assert!(!blocks(&blocklist, "SHOT42"));
+    }
+
+    #[test]
+    fn custom_words_are_folded_too() {
+        let blocklist = Blocklist::new(["L33T", "", "%%"]);
+        assert!(blocks(&blocklist, "xxleetxx"));
+        assert!(!blocks(&blocklist, "%%"));
+    }
+
+    #[test]
+    fn digits_only_spell_words_in_tickets_with_letters() {
+        let blocklist = Blocklist::new(BUILTIN_BLOCKLIST.iter().copied());
+        for ticket in ["455", "717", "8008", "8173", "00455717", "5318008"] {
+            assert!(!blocks(&blocklist, ticket), "{ticket}");
+        }
+        assert!(blocks(&blocklist, "X455"));
+        assert!(blocks(&blocklist, "8O08"));
+        assert!(blocks(&blocklist, "@$$-123"));
+    }
+}
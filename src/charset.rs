@@ -0,0 +1,118 @@
+pub const CAPITALS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+pub const LOWERS: &str = "abcdefghijklmnopqrstuvwxyz";
+pub const NUMBERS: &str = "0123456789";
+pub const SPECIALS: &str = ",.;:\"'!%#";
+
+/// Characters that are easily mistaken for one another when printed or read
+/// aloud, such as `0`/`O`, `1`/`l`/`I` and `5`/`S`, plus punctuation that is
+/// hard to tell apart at small sizes.
+pub const CONFUSABLES: &str = "0OoQ1Iil5S2Z8B,.;:'\"";
+
+/// A named set of characters tickets can be drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharacterClass {
+    pub name: &'static str,
+    pub chars: &'static str,
+}
+
+impl CharacterClass {
+    /// Checks at compile time that `chars` is ASCII, has no duplicates and
+    /// is exactly `size` characters long.
+    const fn new(name: &'static str, chars: &'static str, size: usize) -> Self {
+        assert!(chars.is_ascii(), "built-in classes must be ASCII");
+        assert!(chars.len() == size, "built-in class has the wrong size");
+        assert!(
+            !has_duplicate_bytes(chars.as_bytes()),
+            "built-in class contains a duplicate character"
+        );
+
+        Self { name, chars }
+    }
+}
+
+/// A character class defined by the user, such as hex digits or the
+/// punctuation a particular POS system accepts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomClass {
+    pub name: String,
+    pub chars: String,
+    pub enabled: bool,
+}
+
+/// The classes behind the checkboxes, in the order they are merged.
+pub const BUILTIN_CLASSES: [CharacterClass; 4] = [
+    CharacterClass::new("Capital Letters", CAPITALS, 26),
+    CharacterClass::new("Lowercase Letters", LOWERS, 26),
+    CharacterClass::new("Numbers", NUMBERS, 10),
+    CharacterClass::new("Specials", SPECIALS, 9),
+];
+
+const fn has_duplicate_bytes(bytes: &[u8]) -> bool {
+    let mut i = 0;
+    while i < bytes.len() {
+        let mut j = i + 1;
+        while j < bytes.len() {
+            if bytes[i] == bytes[j] {
+                return true;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capitals_are_the_english_alphabet() {
+        assert_eq!(CAPITALS, "ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+        assert_eq!(CAPITALS, ('A'..='Z').collect::<String>());
+    }
+
+    #[test]
+    fn lowers_are_the_english_alphabet() {
+        assert_eq!(LOWERS, "abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(LOWERS, ('a'..='z').collect::<String>());
+    }
+
+    #[test]
+    fn numbers_are_the_decimal_digits() {
+        assert_eq!(NUMBERS, "0123456789");
+        assert_eq!(NUMBERS, ('0'..='9').collect::<String>());
+    }
+
+    #[test]
+    fn specials_match_the_checkbox_label() {
+        assert_eq!(SPECIALS, ",.;:\"'!%#");
+    }
+
+    #[test]
+    fn builtin_classes_have_no_duplicates() {
+        for class in BUILTIN_CLASSES {
+            assert!(
+                !has_duplicate_bytes(class.chars.as_bytes()),
+                "{} has a duplicate character",
+                class.name
+            );
+        }
+    }
+
+    #[test]
+    fn builtin_classes_do_not_overlap() {
+        let merged: String = BUILTIN_CLASSES.iter().map(|class| class.chars).collect();
+        assert!(!has_duplicate_bytes(merged.as_bytes()));
+        assert_eq!(merged.len(), 26 + 26 + 10 + 9);
+    }
+
+    #[test]
+    fn duplicate_detection() {
+        assert!(has_duplicate_bytes(b"ABCA"));
+        assert!(has_duplicate_bytes(b"ABCDEFGHIJKLMNOPQRSTUVQXYZ"));
+        assert!(!has_duplicate_bytes(b"ABC"));
+        assert!(!has_duplicate_bytes(b""));
+    }
+}
@@ -0,0 +1,470 @@
+//! Check characters that let typos be caught without looking the ticket up.
+//!
+//! Literal characters of a template that are outside an algorithm's
+//! alphabet, like the `EVT-` in `EVT-####`, are skipped when computing or
+//! validating, the same way separators are in ISO 7064. Any other character
+//! outside the alphabet makes a ticket invalid.
+
+use std::{fmt, str::FromStr};
+
+const DIGITS: &str = "0123456789";
+const CAPITALS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const ALPHANUMERICS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+const DAMM_TABLE: [[usize; 10]; 10] = [
+    [0, 3, 1, 7, 5, 9, 8, 6, 4, 2],
+    [7, 0, 9, 2, 1, 5, 4, 8, 6, 3],
+    [4, 2, 0, 6, 8, 7, 1, 3, 5, 9],
+    [1, 7, 5, 0, 9, 8, 3, 4, 2, 6],
+    [6, 1, 2, 3, 0, 4, 5, 9, 7, 8],
+    [3, 6, 7, 4, 2, 0, 9, 5, 8, 1],
+    [5, 8, 6, 9, 7, 2, 0, 1, 3, 4],
+    [8, 9, 4, 5, 3, 6, 2, 0, 1, 7],
+    [9, 4, 3, 8, 6, 1, 7, 2, 0, 5],
+    [2, 5, 8, 1, 4, 3, 6, 7, 9, 0],
+];
+
+const VERHOEFF_MULTIPLICATION: [[usize; 10]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 2, 3, 4, 0, 6, 7, 8, 9, 5],
+    [2, 3, 4, 0, 1, 7, 8, 9, 5, 6],
+    [3, 4, 0, 1, 2, 8, 9, 5, 6, 7],
+    [4, 0, 1, 2, 3, 9, 5, 6, 7, 8],
+    [5, 9, 8, 7, 6, 0, 4, 3, 2, 1],
+    [6, 5, 9, 8, 7, 1, 0, 4, 3, 2],
+    [7, 6, 5, 9, 8, 2, 1, 0, 4, 3],
+    [8, 7, 6, 5, 9, 3, 2, 1, 0, 4],
+    [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+];
+
+const VERHOEFF_PERMUTATION: [[usize; 10]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 5, 7, 6, 2, 8, 3, 0, 9, 4],
+    [5, 8, 0, 3, 7, 9, 6, 1, 4, 2],
+    [8, 9, 1, 6, 0, 4, 3, 5, 2, 7],
+    [9, 4, 5, 3, 1, 2, 6, 8, 7, 0],
+    [4, 2, 8, 6, 5, 7, 3, 9, 0, 1],
+    [2, 7, 9, 3, 8, 0, 6, 4, 1, 5],
+    [7, 0, 4, 6, 9, 1, 3, 2, 5, 8],
+];
+
+const VERHOEFF_INVERSE: [usize; 10] = [0, 4, 3, 2, 1, 5, 6, 7, 8, 9];
+
+/// Algorithm used to compute the check characters appended to each ticket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckAlgorithm {
+    /// Luhn mod N over the ticket's own character set, in the order the
+    /// characters appear in it.
+    LuhnModN,
+    /// Damm over the digits 0-9.
+    Damm,
+    /// Verhoeff over the digits 0-9.
+    Verhoeff,
+    /// ISO 7064 MOD 37-36 over the digits 0-9 and capitals A-Z. Unlike Damm
+    /// and Verhoeff it misses a few swaps of neighbouring characters.
+    Iso7064Mod37_36,
+    /// ISO 7064 MOD 97-10 over the digits 0-9, appending two check digits.
+    Iso7064Mod97_10,
+    /// ISO 7064 MOD 661-26 over the capitals A-Z, appending two check
+    /// letters.
+    Iso7064Mod661_26,
+    /// ISO 7064 MOD 1271-36 over the digits 0-9 and capitals A-Z, appending
+    /// two check characters.
+    Iso7064Mod1271_36,
+}
+
+impl CheckAlgorithm {
+    pub const ALL: [CheckAlgorithm; 7] = [
+        CheckAlgorithm::LuhnModN,
+        CheckAlgorithm::Damm,
+        CheckAlgorithm::Verhoeff,
+        CheckAlgorithm::Iso7064Mod37_36,
+        CheckAlgorithm::Iso7064Mod97_10,
+        CheckAlgorithm::Iso7064Mod661_26,
+        CheckAlgorithm::Iso7064Mod1271_36,
+    ];
+
+    /// The characters this algorithm works over. `character_set` is only
+    /// used by [`CheckAlgorithm::LuhnModN`], the others have a fixed alphabet.
+    pub fn alphabet(&self, character_set: &str) -> Vec<char> {
+        match self {
+            CheckAlgorithm::LuhnModN => {
+                let mut alphabet = Vec::new();
+                for c in character_set.chars() {
+                    if !alphabet.contains(&c) {
+                        alphabet.push(c);
+                    }
+                }
+                alphabet
+            }
+            CheckAlgorithm::Damm | CheckAlgorithm::Verhoeff | CheckAlgorithm::Iso7064Mod97_10 => {
+                DIGITS.chars().collect()
+            }
+            CheckAlgorithm::Iso7064Mod661_26 => CAPITALS.chars().collect(),
+            CheckAlgorithm::Iso7064Mod37_36 | CheckAlgorithm::Iso7064Mod1271_36 => {
+                ALPHANUMERICS.chars().collect()
+            }
+        }
+    }
+
+    /// How many check characters this algorithm appends.
+    pub fn check_length(&self) -> usize {
+        match self.pure_modulus() {
+            Some(_) => 2,
+            None => 1,
+        }
+    }
+
+    /// The modulus of the ISO 7064 pure systems with two check characters.
+    fn pure_modulus(&self) -> Option<usize> {
+        match self {
+            CheckAlgorithm::Iso7064Mod97_10 => Some(97),
+            CheckAlgorithm::Iso7064Mod661_26 => Some(661),
+            CheckAlgorithm::Iso7064Mod1271_36 => Some(1271),
+            _ => None,
+        }
+    }
+
+    /// Computes the check characters for `body`. `literals` are the template
+    /// literals that may be skipped, see [`TicketSpec::literal_chars`].
+    ///
+    /// [`TicketSpec::literal_chars`]: crate::TicketSpec::literal_chars
+    pub fn check_chars(
+        &self,
+        body: &str,
+        character_set: &str,
+        literals: &str,
+    ) -> Result<String, String> {
+        let mut ticket = body.to_owned();
+        self.push_check_chars(
+            &mut ticket,
+            &self.alphabet(character_set),
+            literals,
+            &mut Vec::new(),
+        )?;
+
+        Ok(ticket.split_off(body.len()))
+    }
+
+    /// Like [`CheckAlgorithm::check_chars`], but appends the check
+    /// characters to `ticket` itself. `alphabet` is this algorithm's
+    /// [`CheckAlgorithm::alphabet`], and `values` is scratch space, so a
+    /// caller checking many tickets can reuse both.
+    pub(crate) fn push_check_chars(
+        &self,
+        ticket: &mut String,
+        alphabet: &[char],
+        literals: &str,
+        values: &mut Vec<usize>,
+    ) -> Result<(), String> {
+        if alphabet.len() < 2 {
+            return Err(format!(
+                "{self} needs at least two characters to choose from"
+            ));
+        }
+
+        values.clear();
+        collect_values(ticket, alphabet, literals, values)
+            .map_err(|c| format!("{self} check characters can't cover '{c}'"))?;
+        let radix = alphabet.len();
+        match self {
+            CheckAlgorithm::LuhnModN => ticket.push(alphabet[luhn_check(values, radix)]),
+            CheckAlgorithm::Damm => ticket.push(alphabet[damm(values)]),
+            CheckAlgorithm::Verhoeff => ticket.push(alphabet[verhoeff_check(values)]),
+            CheckAlgorithm::Iso7064Mod37_36 => {
+                ticket.push(alphabet[iso7064_check(values, radix)]);
+            }
+            CheckAlgorithm::Iso7064Mod97_10
+            | CheckAlgorithm::Iso7064Mod661_26
+            | CheckAlgorithm::Iso7064Mod1271_36 => {
+                let modulus = self.pure_modulus().unwrap();
+                let check = iso7064_pure_check(values, modulus, radix);
+                ticket.push(alphabet[check / radix]);
+                ticket.push(alphabet[check % radix]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the last [`CheckAlgorithm::check_length`] characters
+    /// of `ticket` are valid check characters for the rest of it. Pass the
+    /// same character set and literals the ticket was generated with, see
+    /// [`TicketSpec::check_ticket`]. Characters that are neither in the
+    /// alphabet nor among `literals` make the ticket invalid.
+    ///
+    /// [`TicketSpec::check_ticket`]: crate::TicketSpec::check_ticket
+    pub fn validate(&self, ticket: &str, character_set: &str, literals: &str) -> bool {
+        let alphabet = self.alphabet(character_set);
+        let check_length = self.check_length();
+        if alphabet.len() < 2
+            || ticket.chars().count() < check_length
+            || !ticket
+                .chars()
+                .rev()
+                .take(check_length)
+                .all(|c| alphabet.contains(&c))
+        {
+            return false;
+        }
+
+        let mut values = Vec::new();
+        if collect_values(ticket, &alphabet, literals, &mut values).is_err() {
+            return false;
+        }
+        let radix = alphabet.len();
+        match self {
+            CheckAlgorithm::LuhnModN => luhn_sum(&values, radix, false) == 0,
+            CheckAlgorithm::Damm => damm(&values) == 0,
+            CheckAlgorithm::Verhoeff => verhoeff(&values, 0) == 0,
+            CheckAlgorithm::Iso7064Mod37_36 => {
+                let (last, rest) = values.split_last().unwrap();
+                (iso7064(rest, radix) + last) % radix == 1
+            }
+            CheckAlgorithm::Iso7064Mod97_10
+            | CheckAlgorithm::Iso7064Mod661_26
+            | CheckAlgorithm::Iso7064Mod1271_36 => {
+                iso7064_pure(&values, self.pure_modulus().unwrap(), radix) == 1
+            }
+        }
+    }
+}
+
+impl fmt::Display for CheckAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckAlgorithm::LuhnModN => "Luhn mod N",
+            CheckAlgorithm::Damm => "Damm",
+            CheckAlgorithm::Verhoeff => "Verhoeff",
+            CheckAlgorithm::Iso7064Mod37_36 => "ISO 7064 MOD 37-36",
+            CheckAlgorithm::Iso7064Mod97_10 => "ISO 7064 MOD 97-10",
+            CheckAlgorithm::Iso7064Mod661_26 => "ISO 7064 MOD 661-26",
+            CheckAlgorithm::Iso7064Mod1271_36 => "ISO 7064 MOD 1271-36",
+        })
+    }
+}
+
+impl FromStr for CheckAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "luhn" => Ok(CheckAlgorithm::LuhnModN),
+            "damm" => Ok(CheckAlgorithm::Damm),
+            "verhoeff" => Ok(CheckAlgorithm::Verhoeff),
+            "iso7064" | "mod37-36" => Ok(CheckAlgorithm::Iso7064Mod37_36),
+            "mod97-10" => Ok(CheckAlgorithm::Iso7064Mod97_10),
+            "mod661-26" => Ok(CheckAlgorithm::Iso7064Mod661_26),
+            "mod1271-36" => Ok(CheckAlgorithm::Iso7064Mod1271_36),
+            _ => Err(format!(
+                "Unknown check algorithm {s}, expected luhn, damm, verhoeff, iso7064, mod97-10, mod661-26 or mod1271-36"
+            )),
+        }
+    }
+}
+
+/// Appends the index in `alphabet` of each character of `text` to
+/// `values`, skipping `literals` that aren't in it. Returns the first
+/// character that is neither.
+fn collect_values(
+    text: &str,
+    alphabet: &[char],
+    literals: &str,
+    values: &mut Vec<usize>,
+) -> Result<(), char> {
+    for c in text.chars() {
+        match alphabet.iter().position(|a| *a == c) {
+            Some(value) => values.push(value),
+            None if literals.contains(c) => {}
+            None => return Err(c),
+        }
+    }
+
+    Ok(())
+}
+
+/// Luhn mod N sum, doubling every second value counting from the right.
+/// `double_first` is set when the check character hasn't been appended yet.
+fn luhn_sum(values: &[usize], modulus: usize, double_first: bool) -> usize {
+    let mut double = double_first;
+    let mut sum = 0;
+    for value in values.iter().rev() {
+        let mut addend = if double { value * 2 } else { *value };
+        addend = addend / modulus + addend % modulus;
+        sum += addend;
+        double = !double;
+    }
+
+    sum % modulus
+}
+
+fn luhn_check(values: &[usize], modulus: usize) -> usize {
+    (modulus - luhn_sum(values, modulus, true)) % modulus
+}
+
+fn damm(values: &[usize]) -> usize {
+    values
+        .iter()
+        .fold(0, |interim, value| DAMM_TABLE[interim][*value])
+}
+
+/// Verhoeff checksum, with `offset` shifting the permutation used for each
+/// position (1 when computing a check digit, 0 when validating).
+fn verhoeff(values: &[usize], offset: usize) -> usize {
+    values
+        .iter()
+        .rev()
+        .enumerate()
+        .fold(0, |checksum, (i, value)| {
+            VERHOEFF_MULTIPLICATION[checksum][VERHOEFF_PERMUTATION[(i + offset) % 8][*value]]
+        })
+}
+
+fn verhoeff_check(values: &[usize]) -> usize {
+    VERHOEFF_INVERSE[verhoeff(values, 1)]
+}
+
+/// ISO 7064 hybrid MOD (M+1, M) state after processing `values`.
+fn iso7064(values: &[usize], modulus: usize) -> usize {
+    values.iter().fold(modulus, |product, value| {
+        let mut sum = (product + value) % modulus;
+        if sum == 0 {
+            sum = modulus;
+        }
+        (sum * 2) % (modulus + 1)
+    })
+}
+
+fn iso7064_check(values: &[usize], modulus: usize) -> usize {
+    (modulus + 1 - iso7064(values, modulus)) % modulus
+}
+
+/// ISO 7064 pure system remainder of `values` read as a number in base
+/// `radix`.
+fn iso7064_pure(values: &[usize], modulus: usize, radix: usize) -> usize {
+    values
+        .iter()
+        .fold(0, |remainder, value| (remainder * radix + value) % modulus)
+}
+
+/// The two check characters of a pure system, as one number in base `radix`.
+fn iso7064_pure_check(values: &[usize], modulus: usize, radix: usize) -> usize {
+    let remainder = iso7064_pure(values, modulus, radix) * radix * radix % modulus;
+    (modulus + 1 - remainder) % modulus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::{seeded, uniform_index};
+
+    const LETTERS: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+    #[test]
+    fn check_characters_match_known_answers() {
+        let cases = [
+            (CheckAlgorithm::LuhnModN, "7992739871", DIGITS, "3"),
+            (CheckAlgorithm::LuhnModN, "abcdef", "abcdef", "e"),
+            (CheckAlgorithm::Damm, "572", "", "4"),
+            (CheckAlgorithm::Verhoeff, "236", "", "3"),
+            (CheckAlgorithm::Verhoeff, "12345", "", "1"),
+            // The GRid example A1-2425G-ABC1234002-M.
+            (
+                CheckAlgorithm::Iso7064Mod37_36,
+                "A12425GABC1234002",
+                "",
+                "M",
+            ),
+            // The examples from ISO 7064 itself.
+            (CheckAlgorithm::Iso7064Mod97_10, "794", "", "44"),
+            (CheckAlgorithm::Iso7064Mod661_26, "ISOHJ", "", "TC"),
+            (CheckAlgorithm::Iso7064Mod1271_36, "ISO79", "", "3W"),
+        ];
+        for (algorithm, body, character_set, check) in cases {
+            assert_eq!(
+                algorithm.check_chars(body, character_set, ""),
+                Ok(check.to_owned()),
+                "{algorithm} {body}"
+            );
+            assert_eq!(algorithm.check_length(), check.len());
+            assert!(algorithm.validate(&format!("{body}{check}"), character_set, ""));
+        }
+    }
+
+    #[test]
+    fn validate_skips_literals_and_rejects_the_rest() {
+        let damm = CheckAlgorithm::Damm;
+        assert!(damm.validate("5724", "", ""));
+        assert!(damm.validate("EVT-57-24", "", "EVT-"));
+        assert!(!damm.validate("EVT-57-24", "", ""));
+        assert!(!damm.validate("57x24", "", "EVT-"));
+        assert!(!damm.validate("5727", "", ""));
+        assert!(!damm.validate("572-", "", "-"));
+        assert!(!damm.validate("", "", ""));
+        assert_eq!(
+            damm.check_chars("57x2", "", "EVT-"),
+            Err("Damm check characters can't cover 'x'".to_owned())
+        );
+        assert!(!CheckAlgorithm::Iso7064Mod97_10.validate("4", "", ""));
+        // Luhn mod N has nothing to work with in a single character.
+        assert!(!CheckAlgorithm::LuhnModN.validate("AA", "AAA", ""));
+        assert_eq!(
+            CheckAlgorithm::LuhnModN.check_chars("A", "A", ""),
+            Err("Luhn mod N needs at least two characters to choose from".to_owned())
+        );
+    }
+
+    #[test]
+    fn single_substitutions_and_transpositions_are_caught() {
+        let mut rng = seeded(5);
+        for algorithm in CheckAlgorithm::ALL {
+            let alphabet = algorithm.alphabet(LETTERS);
+            let (mut swaps, mut missed) = (0, Vec::new());
+            for _ in 0..50 {
+                let body: String = (0..8)
+                    .map(|_| alphabet[uniform_index(&mut rng, alphabet.len())])
+                    .collect();
+                let check = algorithm.check_chars(&body, LETTERS, "").unwrap();
+                let ticket: Vec<char> = body.chars().chain(check.chars()).collect();
+
+                for i in 0..ticket.len() {
+                    for &c in alphabet.iter().filter(|&&c| c != ticket[i]) {
+                        let mut typo = ticket.clone();
+                        typo[i] = c;
+                        let typo: String = typo.into_iter().collect();
+                        assert!(
+                            !algorithm.validate(&typo, LETTERS, ""),
+                            "{algorithm} {typo}"
+                        );
+                    }
+                }
+
+                for i in 1..ticket.len() {
+                    let (a, b) = (ticket[i - 1], ticket[i]);
+                    // Luhn mod N can't tell the first and last characters
+                    // apart when they're swapped.
+                    let luhn_blind_spot = [alphabet[0], alphabet[alphabet.len() - 1]];
+                    if a == b
+                        || (algorithm == CheckAlgorithm::LuhnModN
+                            && luhn_blind_spot.contains(&a)
+                            && luhn_blind_spot.contains(&b))
+                    {
+                        continue;
+                    }
+                    let mut typo = ticket.clone();
+                    typo.swap(i - 1, i);
+                    let typo: String = typo.into_iter().collect();
+                    swaps += 1;
+                    if algorithm.validate(&typo, LETTERS, "") {
+                        missed.push(typo);
+                    }
+                }
+            }
+
+            match algorithm {
+                CheckAlgorithm::Iso7064Mod37_36 => assert!(missed.len() * 100 < swaps),
+                _ => assert!(missed.is_empty(), "{algorithm} {missed:?}"),
+            }
+        }
+    }
+}
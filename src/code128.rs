@@ -0,0 +1,188 @@
+//! Code 128 barcodes, in code sets B and C.
+
+use crate::barcode::Modules;
+
+/// Bar and space widths of every symbol value, starting with a bar. Each
+/// symbol is 11 modules wide, and the stop symbol 13.
+const PATTERNS: [&[u8]; 107] = [
+    b"212222", b"222122", b"222221", b"121223", b"121322", b"131222", b"122213", b"122312",
+    b"132212", b"221213", b"221312", b"231212", b"112232", b"122132", b"122231", b"113222",
+    b"123122", b"123221", b"223211", b"221132", b"221231", b"213212", b"223112", b"312131",
+    b"311222", b"321122", b"321221", b"312212", b"322112", b"322211", b"212123", b"212321",
+    b"232121", b"111323", b"131123", b"131321", b"112313", b"132113", b"132311", b"211313",
+    b"231113", b"231311", b"112133", b"112331", b"132131", b"113123", b"113321", b"133121",
+    b"313121", b"211331", b"231131", b"213113", b"213311", b"213131", b"311123", b"311321",
+    b"331121", b"312113", b"312311", b"332111", b"314111", b"221411", b"431111", b"111224",
+    b"111422", b"121124", b"121421", b"141122", b"141221", b"112214", b"112412", b"122114",
+    b"122411", b"142112", b"142211", b"241211", b"221114", b"413111", b"241112", b"134111",
+    b"111242", b"121142", b"121241", b"114212", b"124112", b"124211", b"411212", b"421112",
+    b"421211", b"212141", b"214121", b"412121", b"111143", b"111341", b"131141", b"114113",
+    b"114311", b"411113", b"411311", b"113141", b"114131", b"311141", b"411131", b"211412",
+    b"211214", b"211232", b"2331112",
+];
+
+/// The most characters a ticket may have. Code 128 has no limit of its own,
+/// but each character adds 11 modules, and 48 is where GS1-128 stops and
+/// codes get too wide for handheld scanners to read in one sweep.
+pub(crate) const MAX_LENGTH: usize = 48;
+
+const CODE_C: u8 = 99;
+const CODE_B: u8 = 100;
+const START_B: u8 = 104;
+const START_C: u8 = 105;
+const STOP: u8 = 106;
+
+/// Whether Code 128 can hold `c`. Only printable ASCII is allowed, since
+/// that's all code set B has and tickets are meant to be typed back in.
+pub(crate) fn can_encode(c: char) -> bool {
+    matches!(c, ' '..='~')
+}
+
+/// The symbol values of `ticket`, from the start symbol to the check symbol.
+///
+/// Runs of digits switch to code set C, two digits per symbol, where that
+/// makes the code shorter: four or more at either end, or six or more in
+/// between.
+fn values(ticket: &str) -> Result<Vec<u8>, String> {
+    let bytes = ticket.as_bytes();
+    if let Some(c) = ticket.chars().find(|&c| !can_encode(c)) {
+        return Err(format!("Code 128 can't encode {c:?}"));
+    }
+
+    let mut values = Vec::with_capacity(bytes.len() + 3);
+    let mut in_c = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let run = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let at_end = i + run == bytes.len();
+        if run >= 6 || (run >= 4 && (i == 0 || at_end)) || (run == 2 && bytes.len() == 2) {
+            let mut start = i;
+            if run % 2 == 1 {
+                // The odd digit out goes in code set B first.
+                if values.is_empty() {
+                    values.push(START_B);
+                } else if in_c {
+                    values.push(CODE_B);
+                }
+                in_c = false;
+                values.push(bytes[i] - b' ');
+                start += 1;
+            }
+            if values.is_empty() {
+                values.push(START_C);
+            } else if !in_c {
+                values.push(CODE_C);
+            }
+            in_c = true;
+            for pair in bytes[start..i + run].chunks(2) {
+                values.push((pair[0] - b'0') * 10 + pair[1] - b'0');
+            }
+            i += run;
+            continue;
+        }
+
+        if values.is_empty() {
+            values.push(START_B);
+        } else if in_c {
+            values.push(CODE_B);
+        }
+        in_c = false;
+        values.push(bytes[i] - b' ');
+        i += 1;
+    }
+    if values.is_empty() {
+        values.push(START_B);
+    }
+
+    let sum = values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| i.max(1) * value as usize)
+        .sum::<usize>();
+    values.push((sum % 103) as u8);
+
+    Ok(values)
+}
+
+/// Draws `ticket` as bars a quarter as tall as the code is wide.
+pub(crate) fn encode(ticket: &str) -> Result<Modules, String> {
+    let mut widths: Vec<u8> = Vec::new();
+    for value in values(ticket)?.into_iter().chain([STOP]) {
+        widths.extend(PATTERNS[value as usize].iter().map(|width| width - b'0'));
+    }
+
+    let width = widths.iter().map(|&width| width as usize).sum::<usize>();
+    let mut modules = Modules::new(width, (width / 4).max(20));
+    let mut x = 0;
+    for (i, &bar) in widths.iter().enumerate() {
+        if i % 2 == 0 {
+            for column in x..x + bar as usize {
+                modules.set_column(column);
+            }
+        }
+        x += bar as usize;
+    }
+
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digit_runs_use_code_set_c() {
+        assert!(
+            PATTERNS[..106]
+                .iter()
+                .all(|pattern| pattern.iter().map(|b| b - b'0').sum::<u8>() == 11)
+        );
+
+        // Start B, A, B, check.
+        let sum = START_B as usize + 33 + 2 * 34;
+        assert_eq!(values("AB").unwrap(), [START_B, 33, 34, (sum % 103) as u8]);
+
+        let leading = values("123456AB").unwrap();
+        assert_eq!(leading[..6], [START_C, 12, 34, 56, CODE_B, 33]);
+
+        let trailing = values("A12345").unwrap();
+        assert_eq!(trailing[..6], [START_B, 33, 17, CODE_C, 23, 45]);
+
+        assert!(values("AB\u{e9}").is_err());
+    }
+
+    #[test]
+    fn bars_match_the_standard_patterns() {
+        let bars = |ticket| {
+            let modules = encode(ticket).unwrap();
+            (0..modules.width)
+                .map(|x| if modules.is_dark(x, 0) { '1' } else { '0' })
+                .collect::<String>()
+        };
+
+        // Start B, A, B, check 102, stop.
+        assert_eq!(
+            bars("AB"),
+            [
+                "11010010000",
+                "10100011000",
+                "10001011000",
+                "11110101110",
+                "1100011101011",
+            ]
+            .concat()
+        );
+        // Start C, 12, 34, check 82, stop.
+        assert_eq!(
+            bars("1234"),
+            [
+                "11010011100",
+                "10110011100",
+                "10001011000",
+                "10010011110",
+                "1100011101011",
+            ]
+            .concat()
+        );
+    }
+}
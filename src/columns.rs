@@ -0,0 +1,186 @@
+//! Extra columns written next to each ticket.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// One column of the output file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Ticket,
+    /// Position of the ticket in the batch, counting up from `start`.
+    Serial {
+        start: u64,
+    },
+    /// The same batch identifier on every row.
+    BatchId(String),
+    /// When the job started, as an RFC 3339 UTC timestamp.
+    CreatedAt,
+    /// The same ticket type on every row.
+    TicketType(String),
+    /// Any other value that is the same on every row.
+    Constant {
+        header: String,
+        value: String,
+    },
+    /// The ticket's hash. Only hash files, see
+    /// [`TicketSpec::hashes`](crate::TicketSpec), have this column.
+    TicketHash,
+}
+
+/// Everything a row's values come from.
+pub(crate) struct Row<'a> {
+    pub(crate) ticket: &'a str,
+    /// Empty unless the row goes to a hash file.
+    pub(crate) hash: &'a str,
+    /// Position of the ticket in the batch.
+    pub(crate) index: usize,
+    /// When the job started, see [`format_timestamp`].
+    pub(crate) created_at: &'a str,
+}
+
+impl Column {
+    /// The column's name in the header row.
+    pub fn header(&self) -> &str {
+        match self {
+            Column::Ticket => "ticket",
+            Column::Serial { .. } => "serial",
+            Column::BatchId(_) => "batch_id",
+            Column::CreatedAt => "created_at",
+            Column::TicketType(_) => "ticket_type",
+            Column::Constant { header, .. } => header,
+            Column::TicketHash => "ticket_hash",
+        }
+    }
+
+    /// The value on `row`.
+    pub(crate) fn value<'a>(&'a self, row: &Row<'a>) -> Result<Cow<'a, str>, String> {
+        Ok(match self {
+            Column::Ticket => Cow::Borrowed(row.ticket),
+            Column::Serial { start } => Cow::Owned(serial(*start, row.index)?.to_string()),
+            Column::BatchId(value) | Column::TicketType(value) => Cow::Borrowed(value),
+            Column::CreatedAt => Cow::Borrowed(row.created_at),
+            Column::Constant { value, .. } => Cow::Borrowed(value),
+            Column::TicketHash => Cow::Borrowed(row.hash),
+        })
+    }
+}
+
+/// The serial number of the `index`th ticket when counting up from `start`.
+pub(crate) fn serial(start: u64, index: usize) -> Result<u64, String> {
+    u64::try_from(index)
+        .ok()
+        .and_then(|index| start.checked_add(index))
+        .ok_or_else(|| format!("Serial numbers from {start} run past {}", u64::MAX))
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Column::Ticket => write!(f, "Ticket"),
+            Column::Serial { start } => write!(f, "Serial from {start}"),
+            Column::BatchId(value) => write!(f, "Batch ID {value}"),
+            Column::CreatedAt => write!(f, "Creation time"),
+            Column::TicketType(value) => write!(f, "Ticket type {value}"),
+            Column::Constant { header, value } => write!(f, "{header} = {value}"),
+            Column::TicketHash => write!(f, "Ticket hash"),
+        }
+    }
+}
+
+/// Parses the command line form: `ticket`, `serial` or `serial=START`,
+/// `batch-id=ID`, `created-at`, `ticket-type=TYPE`, or `HEADER=VALUE` for a
+/// constant column.
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+
+        match (name.to_ascii_lowercase().as_str(), value) {
+            ("ticket", None) => Ok(Column::Ticket),
+            ("serial", None) => Ok(Column::Serial { start: 1 }),
+            ("serial", Some(start)) => start
+                .parse()
+                .map(|start| Column::Serial { start })
+                .map_err(|err| format!("Invalid serial start {start}: {err}")),
+            ("batch-id", Some(value)) => Ok(Column::BatchId(value.to_owned())),
+            ("created-at", None) => Ok(Column::CreatedAt),
+            ("ticket-type", Some(value)) => Ok(Column::TicketType(value.to_owned())),
+            (_, Some(value)) => Ok(Column::Constant {
+                header: name.to_owned(),
+                value: value.to_owned(),
+            }),
+            _ => Err(format!(
+                "Unknown column {s}, expected ticket, serial[=START], batch-id=ID, created-at, ticket-type=TYPE or HEADER=VALUE"
+            )),
+        }
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp with whole seconds.
+pub(crate) fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's
+    // days_from_civil inverse, shifted so years start in March.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn timestamps_are_rfc3339() {
+        let at = |seconds| format_timestamp(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn columns_parse_from_the_command_line_form() {
+        assert_eq!("serial=100".parse(), Ok(Column::Serial { start: 100 }));
+        assert_eq!("created-at".parse(), Ok(Column::CreatedAt));
+        assert_eq!(
+            "venue=Main Hall".parse(),
+            Ok(Column::Constant {
+                header: "venue".to_owned(),
+                value: "Main Hall".to_owned(),
+            })
+        );
+        assert!("nonsense".parse::<Column>().is_err());
+    }
+}
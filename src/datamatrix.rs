@@ -0,0 +1,405 @@
+//! DataMatrix (ECC 200) codes in ASCII encodation, square sizes up to 48×48.
+
+use crate::{barcode::Modules, reed_solomon::Field};
+
+/// A square symbol size: its width, data and check codewords, and data
+/// regions per side with their width.
+struct Size {
+    width: usize,
+    data_words: usize,
+    check_words: usize,
+    regions: usize,
+    region_width: usize,
+}
+
+const fn size(width: usize, data_words: usize, check_words: usize, regions: usize) -> Size {
+    Size {
+        width,
+        data_words,
+        check_words,
+        regions,
+        region_width: width / regions - 2,
+    }
+}
+
+/// The sizes with a single block of check words, smallest first.
+const SIZES: [Size; 14] = [
+    size(10, 3, 5, 1),
+    size(12, 5, 7, 1),
+    size(14, 8, 10, 1),
+    size(16, 12, 12, 1),
+    size(18, 18, 14, 1),
+    size(20, 22, 18, 1),
+    size(22, 30, 20, 1),
+    size(24, 36, 24, 1),
+    size(26, 44, 28, 1),
+    size(32, 62, 36, 2),
+    size(36, 86, 42, 2),
+    size(40, 114, 48, 2),
+    size(44, 144, 56, 2),
+    size(48, 174, 68, 2),
+];
+
+/// The most data codewords a symbol holds. Each byte takes at most two.
+pub(crate) const MAX_DATA_WORDS: usize = SIZES[SIZES.len() - 1].data_words;
+
+/// Pads the unused data codewords.
+const PAD: u8 = 129;
+/// Shifts the next codeword to the upper half of the byte range.
+const UPPER_SHIFT: u8 = 235;
+
+/// The data codewords of `ticket`'s UTF-8 bytes. Digit pairs take one
+/// codeword, other ASCII one, and other bytes two.
+fn data_words(ticket: &str) -> Vec<u8> {
+    let bytes = ticket.as_bytes();
+    let mut words = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if byte.is_ascii_digit() && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+            words.push(130 + (byte - b'0') * 10 + bytes[i + 1] - b'0');
+            i += 2;
+            continue;
+        }
+        if byte < 128 {
+            words.push(byte + 1);
+        } else {
+            words.extend([UPPER_SHIFT, byte - 127]);
+        }
+        i += 1;
+    }
+
+    words
+}
+
+/// Draws `ticket` in the smallest square symbol that holds it.
+pub(crate) fn encode(ticket: &str) -> Result<Modules, String> {
+    let mut words = data_words(ticket);
+    let size = SIZES
+        .iter()
+        .find(|size| size.data_words >= words.len())
+        .ok_or_else(|| format!("{ticket} is too long for a DataMatrix code"))?;
+
+    // The first pad is plain, and the rest are scrambled by their position.
+    if words.len() < size.data_words {
+        words.push(PAD);
+    }
+    while words.len() < size.data_words {
+        let position = words.len() + 1;
+        let mut pad = PAD as usize + (149 * position) % 253 + 1;
+        if pad > 254 {
+            pad -= 254;
+        }
+        words.push(pad as u8);
+    }
+
+    let field = Field::new(0x12D, 256);
+    let data: Vec<u16> = words.iter().map(|&word| word as u16).collect();
+    words.extend(
+        field
+            .check_words(&data, size.check_words)
+            .into_iter()
+            .map(|word| word as u8),
+    );
+
+    let mapping = place(&words, size.regions * size.region_width);
+    Ok(draw(&mapping, size))
+}
+
+/// Places the bits of `words` in a `side`×`side` grid, following the
+/// diagonal order of ISO/IEC 16022 Annex F.
+fn place(words: &[u8], side: usize) -> Vec<bool> {
+    let mut placement = Placement {
+        words,
+        side: side as isize,
+        grid: vec![None; side * side],
+    };
+    placement.run();
+
+    placement
+        .grid
+        .into_iter()
+        .map(|bit| bit.unwrap_or(false))
+        .collect()
+}
+
+struct Placement<'a> {
+    words: &'a [u8],
+    side: isize,
+    grid: Vec<Option<bool>>,
+}
+
+impl Placement<'_> {
+    fn run(&mut self) {
+        let n = self.side;
+        let (mut row, mut column, mut word) = (4, 0, 0);
+        loop {
+            if row == n && column == 0 {
+                self.corner(
+                    word,
+                    [
+                        (n - 1, 0),
+                        (n - 1, 1),
+                        (n - 1, 2),
+                        (0, n - 2),
+                        (0, n - 1),
+                        (1, n - 1),
+                        (2, n - 1),
+                        (3, n - 1),
+                    ],
+                );
+                word += 1;
+            }
+            if row == n - 2 && column == 0 && n % 4 != 0 {
+                self.corner(
+                    word,
+                    [
+                        (n - 3, 0),
+                        (n - 2, 0),
+                        (n - 1, 0),
+                        (0, n - 4),
+                        (0, n - 3),
+                        (0, n - 2),
+                        (0, n - 1),
+                        (1, n - 1),
+                    ],
+                );
+                word += 1;
+            }
+            if row == n - 2 && column == 0 && n % 8 == 4 {
+                self.corner(
+                    word,
+                    [
+                        (n - 3, 0),
+                        (n - 2, 0),
+                        (n - 1, 0),
+                        (0, n - 2),
+                        (0, n - 1),
+                        (1, n - 1),
+                        (2, n - 1),
+                        (3, n - 1),
+                    ],
+                );
+                word += 1;
+            }
+            if row == n + 4 && column == 2 && n % 8 == 0 {
+                self.corner(
+                    word,
+                    [
+                        (n - 1, 0),
+                        (n - 1, n - 1),
+                        (0, n - 3),
+                        (0, n - 2),
+                        (0, n - 1),
+                        (1, n - 3),
+                        (1, n - 2),
+                        (1, n - 1),
+                    ],
+                );
+                word += 1;
+            }
+
+            // Sweep up and to the right, then down and to the left.
+            loop {
+                if row < n && column >= 0 && self.grid[(row * n + column) as usize].is_none() {
+                    self.utah(row, column, word);
+                    word += 1;
+                }
+                row -= 2;
+                column += 2;
+                if row < 0 || column >= n {
+                    break;
+                }
+            }
+            row += 1;
+            column += 3;
+            loop {
+                if row >= 0 && column < n && self.grid[(row * n + column) as usize].is_none() {
+                    self.utah(row, column, word);
+                    word += 1;
+                }
+                row += 2;
+                column -= 2;
+                if row >= n || column < 0 {
+                    break;
+                }
+            }
+            row += 3;
+            column += 1;
+
+            if row >= n && column >= n {
+                break;
+            }
+        }
+
+        // Sizes that leave the bottom right corner empty fill it with a
+        // fixed pattern.
+        let last = (n * n - 1) as usize;
+        if self.grid[last].is_none() {
+            self.grid[last] = Some(true);
+            self.grid[last - n as usize - 1] = Some(true);
+        }
+    }
+
+    /// Places bit `bit` (1 is the most significant) of codeword `word`,
+    /// wrapping positions that fall off the top or left edge.
+    fn module(&mut self, mut row: isize, mut column: isize, word: usize, bit: u8) {
+        let n = self.side;
+        if row < 0 {
+            row += n;
+            column += 4 - (n + 4) % 8;
+        }
+        if column < 0 {
+            column += n;
+            row += 4 - (n + 4) % 8;
+        }
+        let dark = self.words[word] & (0x80 >> (bit - 1)) != 0;
+        self.grid[(row * n + column) as usize] = Some(dark);
+    }
+
+    /// Places codeword `word` in the usual L shape, with its last bit at
+    /// `(row, column)`.
+    fn utah(&mut self, row: isize, column: isize, word: usize) {
+        self.module(row - 2, column - 2, word, 1);
+        self.module(row - 2, column - 1, word, 2);
+        self.module(row - 1, column - 2, word, 3);
+        self.module(row - 1, column - 1, word, 4);
+        self.module(row - 1, column, word, 5);
+        self.module(row, column - 2, word, 6);
+        self.module(row, column - 1, word, 7);
+        self.module(row, column, word, 8);
+    }
+
+    /// Places codeword `word` split across the corners, bit by bit.
+    fn corner(&mut self, word: usize, positions: [(isize, isize); 8]) {
+        for (bit, (row, column)) in (1..).zip(positions) {
+            self.module(row, column, word, bit);
+        }
+    }
+}
+
+/// Lays the data regions of `mapping` out in `size`, each with its solid
+/// left and bottom edges and dotted top and right ones.
+fn draw(mapping: &[bool], size: &Size) -> Modules {
+    let mut modules = Modules::new(size.width, size.width);
+    let side = size.regions * size.region_width;
+    let block = size.region_width + 2;
+
+    for region_y in 0..size.regions {
+        for region_x in 0..size.regions {
+            let (left, top) = (region_x * block, region_y * block);
+            for k in 0..block {
+                modules.set(left, top + k);
+                modules.set(left + k, top + block - 1);
+                if k % 2 == 0 {
+                    modules.set(left + k, top);
+                } else {
+                    modules.set(left + block - 1, top + k);
+                }
+            }
+        }
+    }
+
+    for row in 0..side {
+        for column in 0..side {
+            if mapping[row * side + column] {
+                let y = row / size.region_width * block + 1 + row % size.region_width;
+                let x = column / size.region_width * block + 1 + column % size.region_width;
+                modules.set(x, y);
+            }
+        }
+    }
+
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_standard_example() {
+        // ISO/IEC 16022 encodes 123456 as these data and check codewords.
+        let words: Vec<u16> = data_words("123456").into_iter().map(u16::from).collect();
+        assert_eq!(words, [142, 164, 186]);
+        let field = Field::new(0x12D, 256);
+        assert_eq!(field.check_words(&words, 5), [114, 25, 5, 88, 102]);
+
+        let modules = encode("123456").unwrap();
+        assert_eq!(modules.width, 10);
+        assert_eq!(encode("AB12CD34EF").unwrap().width, 14);
+        assert_eq!(encode(&"A".repeat(100)).unwrap().width, 40);
+        assert!(encode(&"A".repeat(200)).is_err());
+    }
+
+    fn rows(modules: &Modules) -> Vec<String> {
+        (0..modules.height)
+            .map(|y| {
+                (0..modules.width)
+                    .map(|x| if modules.is_dark(x, y) { 'X' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draws_whole_symbols() {
+        // The finder and clock tracks around the codewords, placed as in
+        // ISO/IEC 16022 Annex F: the standard example above, `A` with a
+        // plain and a scrambled pad, and `ABCD`, whose 12×12 symbol leaves
+        // the corner pattern in the bottom right of its data region.
+        let cases: [(&str, &[&str]); 3] = [
+            (
+                "123456",
+                &[
+                    "X.X.X.X.X.",
+                    "XX..X.XX.X",
+                    "XX.....X..",
+                    "XX...XXX.X",
+                    "XX....X...",
+                    "X.....XXXX",
+                    "XXX.XX....",
+                    "XXXX.XX..X",
+                    "X..XXX.X..",
+                    "XXXXXXXXXX",
+                ],
+            ),
+            (
+                "A",
+                &[
+                    "X.X.X.X.X.",
+                    "XX.XX...XX",
+                    "X...XX.X..",
+                    "X..XX.X.XX",
+                    "X..X.X....",
+                    "X..X..X.XX",
+                    "XX.X..XX..",
+                    "XX..XXXX.X",
+                    "XX....X...",
+                    "XXXXXXXXXX",
+                ],
+            ),
+            (
+                "ABCD",
+                &[
+                    "X.X.X.X.X.X.",
+                    "X.XX......XX",
+                    "X...X.X.X...",
+                    "X.XX......XX",
+                    "X..X..XXXX..",
+                    "X..XX.X.X..X",
+                    "X.XX.X...X..",
+                    "X..XX.XXX.XX",
+                    "X.....XX....",
+                    "X.XX.XXXXX.X",
+                    "X....XXX..X.",
+                    "XXXXXXXXXXXX",
+                ],
+            ),
+        ];
+
+        for (ticket, expected) in cases {
+            assert_eq!(rows(&encode(ticket).unwrap()), expected, "{ticket}");
+        }
+    }
+}
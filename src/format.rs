@@ -0,0 +1,491 @@
+//! File formats a batch can be written in.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use rust_xlsxwriter::{ColNum, Format, IgnoreError, Workbook, XlsxError};
+
+use crate::{
+    Column,
+    columns::{Row, serial},
+};
+
+/// Rows in an Excel worksheet, including the header.
+pub(crate) const XLSX_MAX_ROWS: usize = 1_048_576;
+
+/// How the batch is laid out on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// One row per ticket, with a header row if
+    /// [`TicketSpec::header`](crate::TicketSpec) is set.
+    #[default]
+    Csv,
+    /// An array holding one object per ticket, keyed by column name.
+    Json,
+    /// One object per line, keyed by column name.
+    JsonLines,
+    /// Just the tickets, one per line. Other columns and the header are left
+    /// out, except that hash files put the hash after a tab if they have the
+    /// ticket too.
+    Text,
+    /// An `INSERT` statement per ticket into `table`, wrapped in a
+    /// transaction. A dotted name like `events.tickets` is quoted part by
+    /// part.
+    Sql { table: String },
+    /// An Excel workbook with one row per ticket on its first sheet, stored
+    /// as text so leading zeros and long numbers survive, and a summary of
+    /// the spec on the second.
+    Xlsx,
+}
+
+impl OutputFormat {
+    /// Every format, with [`OutputFormat::Sql`] writing to a `tickets` table.
+    pub fn all() -> [OutputFormat; 6] {
+        [
+            OutputFormat::Csv,
+            OutputFormat::Json,
+            OutputFormat::JsonLines,
+            OutputFormat::Text,
+            OutputFormat::Sql {
+                table: "tickets".to_owned(),
+            },
+            OutputFormat::Xlsx,
+        ]
+    }
+
+    /// The usual file extension, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::JsonLines => "jsonl",
+            OutputFormat::Text => "txt",
+            OutputFormat::Sql { .. } => "sql",
+            OutputFormat::Xlsx => "xlsx",
+        }
+    }
+
+    /// The format whose [`OutputFormat::extension`] `file_path` has, if any.
+    pub fn from_path(file_path: &str) -> Option<OutputFormat> {
+        let extension = Path::new(file_path).extension()?.to_str()?;
+        OutputFormat::all()
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Csv => "CSV",
+            OutputFormat::Json => "JSON",
+            OutputFormat::JsonLines => "JSON Lines",
+            OutputFormat::Text => "Text",
+            OutputFormat::Sql { .. } => "SQL",
+            OutputFormat::Xlsx => "Excel",
+        })
+    }
+}
+
+/// Parses the command line form: `csv`, `json`, `jsonl`, `txt`, `sql`, which
+/// writes to a `tickets` table, or `xlsx`.
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" | "ndjson" => Ok(OutputFormat::JsonLines),
+            "txt" | "text" => Ok(OutputFormat::Text),
+            "sql" => Ok(OutputFormat::Sql {
+                table: "tickets".to_owned(),
+            }),
+            "xlsx" | "excel" => Ok(OutputFormat::Xlsx),
+            _ => Err(format!(
+                "Unknown format {s}, expected csv, json, jsonl, txt, sql or xlsx"
+            )),
+        }
+    }
+}
+
+/// Writes rows in one [`OutputFormat`].
+pub(crate) enum RowWriter<W: Write + Send> {
+    Csv(Box<csv::Writer<W>>),
+    Json(BufWriter<W>),
+    JsonLines(BufWriter<W>),
+    Text(BufWriter<W>),
+    /// `insert` is everything before the values, which is the same for every
+    /// row.
+    Sql {
+        out: BufWriter<W>,
+        insert: String,
+    },
+    /// The workbook is only written to `out` by [`RowWriter::finish`], but
+    /// its ticket sheet keeps little in memory. `first_row` is 1 when there
+    /// is a header.
+    Xlsx {
+        workbook: Box<Workbook>,
+        out: W,
+        first_row: u32,
+    },
+}
+
+impl<W: Write + Send> RowWriter<W> {
+    /// Starts a file of rows with `columns`. `summary` lists what the batch
+    /// was generated from, for formats that have room for it.
+    pub(crate) fn new(
+        format: &OutputFormat,
+        out: W,
+        columns: &[Column],
+        header: bool,
+        summary: &[(&str, String)],
+    ) -> io::Result<Self> {
+        let mut writer = match format {
+            OutputFormat::Csv => RowWriter::Csv(Box::new(csv::Writer::from_writer(out))),
+            OutputFormat::Json => RowWriter::Json(BufWriter::new(out)),
+            OutputFormat::JsonLines => RowWriter::JsonLines(BufWriter::new(out)),
+            OutputFormat::Text => RowWriter::Text(BufWriter::new(out)),
+            OutputFormat::Sql { table } => {
+                let mut insert = String::from("INSERT INTO ");
+                for (i, part) in table.split('.').enumerate() {
+                    if i > 0 {
+                        insert.push('.');
+                    }
+                    push_sql_identifier(&mut insert, part);
+                }
+                insert.push_str(" (");
+                for (i, column) in columns.iter().enumerate() {
+                    if i > 0 {
+                        insert.push_str(", ");
+                    }
+                    push_sql_identifier(&mut insert, column.header());
+                }
+                insert.push_str(") VALUES (");
+
+                RowWriter::Sql {
+                    out: BufWriter::new(out),
+                    insert,
+                }
+            }
+            OutputFormat::Xlsx => RowWriter::Xlsx {
+                workbook: Box::new(
+                    start_workbook(columns, header, summary).map_err(io::Error::other)?,
+                ),
+                out,
+                first_row: u32::from(header),
+            },
+        };
+
+        match &mut writer {
+            RowWriter::Csv(csv_writer) if header => {
+                csv_writer.write_record(columns.iter().map(Column::header))?;
+            }
+            RowWriter::Json(out) => out.write_all(b"[")?,
+            RowWriter::Sql { out, .. } => out.write_all(b"BEGIN;\n")?,
+            _ => {}
+        }
+
+        Ok(writer)
+    }
+
+    /// Writes the values of `columns` on `row`.
+    pub(crate) fn write_row(&mut self, columns: &[Column], row: &Row) -> io::Result<()> {
+        let values = columns
+            .iter()
+            .map(|column| column.value(row))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io::Error::other)?;
+        let index = row.index;
+        let lines = matches!(self, RowWriter::JsonLines(_));
+        match self {
+            RowWriter::Csv(csv_writer) => {
+                for value in values {
+                    csv_writer.write_field(value.as_bytes())?;
+                }
+                csv_writer.write_record(None::<&[u8]>)?;
+            }
+            RowWriter::Json(out) | RowWriter::JsonLines(out) => {
+                let mut object = String::from(match (lines, index) {
+                    (true, _) => "{",
+                    (false, 0) => "\n  {",
+                    (false, _) => ",\n  {",
+                });
+                for (i, (column, value)) in columns.iter().zip(values).enumerate() {
+                    if i > 0 {
+                        object.push_str(", ");
+                    }
+                    push_json_string(&mut object, column.header());
+                    object.push_str(": ");
+                    push_json_string(&mut object, &value);
+                }
+                object.push('}');
+                if lines {
+                    object.push('\n');
+                }
+                out.write_all(object.as_bytes())?;
+            }
+            RowWriter::Text(out) => {
+                let mut line = String::new();
+                for (column, value) in columns.iter().zip(values) {
+                    if matches!(column, Column::Ticket | Column::TicketHash) {
+                        if !line.is_empty() {
+                            line.push('\t');
+                        }
+                        line.push_str(&value);
+                    }
+                }
+                writeln!(out, "{line}")?;
+            }
+            RowWriter::Sql { out, insert } => {
+                let mut statement = String::new();
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        statement.push_str(", ");
+                    }
+                    statement.push('\'');
+                    statement.push_str(&value.replace('\'', "''"));
+                    statement.push('\'');
+                }
+                statement.push_str(");\n");
+                out.write_all(insert.as_bytes())?;
+                out.write_all(statement.as_bytes())?;
+            }
+            RowWriter::Xlsx {
+                workbook,
+                first_row,
+                ..
+            } => {
+                let row_number = u32::try_from(index)
+                    .ok()
+                    .and_then(|index| index.checked_add(*first_row))
+                    .filter(|row_number| (*row_number as usize) < XLSX_MAX_ROWS)
+                    .ok_or_else(|| io::Error::other("Too many rows for an Excel worksheet"))?;
+                write_xlsx_row(workbook, row_number, columns, values.into_iter(), index)
+                    .map_err(io::Error::other)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Closes off the file and flushes it.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        match self {
+            RowWriter::Csv(csv_writer) => csv_writer.flush(),
+            RowWriter::Json(out) => {
+                out.write_all(b"\n]\n")?;
+                out.flush()
+            }
+            RowWriter::JsonLines(out) | RowWriter::Text(out) => out.flush(),
+            RowWriter::Sql { out, .. } => {
+                out.write_all(b"COMMIT;\n")?;
+                out.flush()
+            }
+            RowWriter::Xlsx { workbook, out, .. } => {
+                workbook
+                    .save_to_writer(&mut *out)
+                    .map_err(io::Error::other)?;
+                out.flush()
+            }
+        }
+    }
+}
+
+/// A workbook with an empty ticket sheet, headed by `columns` if `header` is
+/// set, followed by a sheet listing `summary`.
+fn start_workbook(
+    columns: &[Column],
+    header: bool,
+    summary: &[(&str, String)],
+) -> Result<Workbook, XlsxError> {
+    let bold = Format::new().set_bold();
+    let mut workbook = Workbook::new();
+
+    let tickets = workbook.add_worksheet_with_constant_memory();
+    tickets.set_name("Tickets")?;
+    if let Some(last_column) = columns.len().checked_sub(1) {
+        // Excel flags text that looks like a number, which is the point here.
+        tickets.ignore_error_range(
+            0,
+            0,
+            XLSX_MAX_ROWS as u32 - 1,
+            last_column as ColNum,
+            IgnoreError::NumberStoredAsText,
+        )?;
+    }
+    if header {
+        for (i, column) in columns.iter().enumerate() {
+            tickets.write_string_with_format(0, i as ColNum, column.header(), &bold)?;
+        }
+    }
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Summary")?;
+    for (row, (label, value)) in summary.iter().enumerate() {
+        sheet.write_string_with_format(row as u32, 0, *label, &bold)?;
+        sheet.write_string(row as u32, 1, value)?;
+    }
+    sheet.autofit();
+
+    Ok(workbook)
+}
+
+/// Writes the `index`th ticket's `values` to `row` of the ticket sheet.
+/// Serial numbers are stored as numbers and everything else as text.
+fn write_xlsx_row<'a>(
+    workbook: &mut Workbook,
+    row: u32,
+    columns: &[Column],
+    values: impl Iterator<Item = Cow<'a, str>>,
+    index: usize,
+) -> Result<(), XlsxError> {
+    let tickets = workbook.worksheet_from_index(0)?;
+    for (i, (column, value)) in columns.iter().zip(values).enumerate() {
+        let col = i as ColNum;
+        match column {
+            Column::Serial { start } => {
+                let serial = serial(*start, index).map_err(XlsxError::ParameterError)?;
+                tickets.write_number(row, col, serial as f64)?;
+            }
+            _ => {
+                // Sized once from the first row, since the sheet can't be
+                // autofitted without keeping every row.
+                if index == 0 {
+                    let width = value.chars().count().max(column.header().len()) + 2;
+                    tickets.set_column_width(col, width as f64)?;
+                }
+                tickets.write_string(row, col, value)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends `name` as a double-quoted SQL identifier.
+fn push_sql_identifier(buf: &mut String, name: &str) {
+    buf.push('"');
+    buf.push_str(&name.replace('"', "\"\""));
+    buf.push('"');
+}
+
+/// Appends `value` as a JSON string.
+fn push_json_string(buf: &mut String, value: &str) {
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if c < ' ' => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn write(format: OutputFormat, tickets: &[&str]) -> String {
+        let columns = [Column::Ticket, Column::Serial { start: 1 }];
+        String::from_utf8(write_bytes(&format, &columns, true, tickets)).unwrap()
+    }
+
+    fn write_bytes(
+        format: &OutputFormat,
+        columns: &[Column],
+        header: bool,
+        tickets: &[&str],
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        let summary = [("Seed", "1".to_owned())];
+        let mut writer = RowWriter::new(format, &mut out, columns, header, &summary).unwrap();
+        for (index, ticket) in tickets.iter().enumerate() {
+            let row = Row {
+                ticket,
+                hash: "9f86d081",
+                index,
+                created_at: "",
+            };
+            writer.write_row(columns, &row).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+
+        out
+    }
+
+    #[test]
+    fn formats_escape_their_values() {
+        let tickets = ["A\"1", "B'2"];
+        assert_eq!(
+            write(OutputFormat::Csv, &tickets),
+            "ticket,serial\n\"A\"\"1\",1\nB'2,2\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Json, &tickets),
+            "[\n  {\"ticket\": \"A\\\"1\", \"serial\": \"1\"},\n  {\"ticket\": \"B'2\", \"serial\": \"2\"}\n]\n"
+        );
+        assert_eq!(
+            write(OutputFormat::JsonLines, &tickets),
+            "{\"ticket\": \"A\\\"1\", \"serial\": \"1\"}\n{\"ticket\": \"B'2\", \"serial\": \"2\"}\n"
+        );
+        assert_eq!(write(OutputFormat::Text, &tickets), "A\"1\nB'2\n");
+        assert_eq!(
+            write(
+                OutputFormat::Sql {
+                    table: "events.tickets".to_owned()
+                },
+                &tickets
+            ),
+            "BEGIN;\n\
+             INSERT INTO \"events\".\"tickets\" (\"ticket\", \"serial\") VALUES ('A\"1', '1');\n\
+             INSERT INTO \"events\".\"tickets\" (\"ticket\", \"serial\") VALUES ('B''2', '2');\n\
+             COMMIT;\n"
+        );
+        assert_eq!(write(OutputFormat::Json, &[]), "[\n]\n");
+    }
+
+    #[test]
+    fn xlsx_stores_tickets_as_text() {
+        let columns = [Column::Serial { start: 1 }, Column::Ticket];
+        let out = write_bytes(&OutputFormat::Xlsx, &columns, false, &["000123"]);
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(out)).unwrap();
+        let mut entry = |name| {
+            let mut contents = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            contents
+        };
+
+        let workbook = entry("xl/workbook.xml");
+        let tickets = workbook.find(r#"<sheet name="Tickets""#).unwrap();
+        let summary = workbook.find(r#"<sheet name="Summary""#).unwrap();
+        assert!(tickets < summary);
+
+        // The serial is a number, and the ticket an inline or shared string
+        // so its leading zeros survive.
+        let sheet = entry("xl/worksheets/sheet1.xml");
+        assert!(sheet.contains(r#"<c r="A1"><v>1</v></c>"#), "{sheet}");
+        assert!(
+            sheet.contains(r#"<c r="B1" t="inlineStr"><is><t>000123</t></is></c>"#)
+                || sheet.contains(r#"<c r="B1" t="s">"#),
+            "{sheet}"
+        );
+    }
+}
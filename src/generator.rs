@@ -0,0 +1,602 @@
+use rand::RngCore;
+use rand_chacha::ChaCha20Rng;
+
+use crate::{
+    CheckAlgorithm, GenerationMode, TicketSpec,
+    blocklist::Blocklist,
+    issued::IssuedSet,
+    limits::ClassSampler,
+    permutation::Counter,
+    rng::{random_seed, seeded, uniform_index},
+    shard::Shards,
+    template::Position,
+};
+
+/// Yields the unique tickets described by a [`TicketSpec`].
+#[derive(Debug)]
+pub struct Generator<R = ChaCha20Rng> {
+    rng: R,
+    seed: Option<u64>,
+    layout: Layout,
+    /// Set in [`GenerationMode::Sharded`], which draws from copies of
+    /// `layout` on several threads instead.
+    shards: Option<Shards>,
+    check: Option<CheckCharacter>,
+    blocklist: Option<Blocklist>,
+    scratch: Scratch,
+    rejected: usize,
+    issued: IssuedSet,
+    /// Whether new tickets go into `issued`, rather than being unable to
+    /// repeat or being tracked by `shards`.
+    tracks_issued: bool,
+    preloaded: usize,
+    /// Earlier and new tickets so far, out of `max_ticket_count`.
+    taken: u128,
+    max_ticket_count: u128,
+    remaining: usize,
+}
+
+impl Generator {
+    /// Seeds the generator with [`TicketSpec::seed`], or with a fresh random
+    /// seed when the spec doesn't have one.
+    pub fn new(spec: &TicketSpec) -> Result<Self, String> {
+        let seed = spec.seed.unwrap_or_else(random_seed);
+        Self::build(spec, seeded(seed), Some(seed))
+    }
+}
+
+impl<R: RngCore> Generator<R> {
+    /// Draws tickets from `rng` instead of the seeded ChaCha20 generator.
+    /// Permuted and sharded generation derive everything from the seed, so
+    /// they need [`Generator::new`] instead.
+    pub fn with_rng(spec: &TicketSpec, rng: R) -> Result<Self, String> {
+        Self::build(spec, rng, None)
+    }
+
+    fn build(spec: &TicketSpec, rng: R, seed: Option<u64>) -> Result<Self, String> {
+        spec.validate()?;
+
+        let layout = match (spec.mode, seed) {
+            (GenerationMode::Permuted { start }, Some(seed)) => {
+                Layout::Permuted(Counter::new(seed, spec.positions()?, start))
+            }
+            (GenerationMode::Permuted { .. } | GenerationMode::Sharded { .. }, None) => {
+                return Err(format!("{} generation needs a seed", spec.mode));
+            }
+            (GenerationMode::Random | GenerationMode::Sharded { .. }, _) => {
+                match spec.class_sampler()? {
+                    Some(sampler) => Layout::Classes(sampler),
+                    None => Layout::Positions(spec.positions()?),
+                }
+            }
+        };
+        let capacity = spec.ticket_count + spec.issued_tickets.len();
+        let shards = match (spec.mode, seed) {
+            (GenerationMode::Sharded { threads }, Some(seed)) => Some(Shards::new(
+                seed,
+                layout.clone(),
+                threads,
+                capacity,
+                layout.key_space(),
+            )),
+            _ => None,
+        };
+        // Permuted tickets can't repeat and sharded ones are tracked by the
+        // shards, so neither needs remembering here.
+        let tracks_issued = shards.is_none() && !matches!(layout, Layout::Permuted(_));
+        let issued = IssuedSet::new(
+            spec.min_distance,
+            if tracks_issued {
+                capacity
+            } else {
+                spec.issued_tickets.len()
+            },
+            layout.key_space(),
+        );
+
+        let mut generator = Self {
+            rng,
+            seed,
+            layout,
+            shards,
+            check: spec.check_algorithm.map(|algorithm| CheckCharacter {
+                algorithm,
+                alphabet: algorithm.alphabet(&spec.check_character_set()),
+                literals: spec.literal_chars(),
+            }),
+            blocklist: spec.blocklist(),
+            scratch: Scratch::default(),
+            rejected: 0,
+            issued,
+            tracks_issued,
+            preloaded: 0,
+            taken: 0,
+            max_ticket_count: spec.max_ticket_count(),
+            remaining: spec.ticket_count,
+        };
+        generator.preload(spec);
+        generator.taken = generator.preloaded as u128;
+
+        if spec.min_distance <= 1 && generator.preloaded > 0 {
+            let left = generator
+                .max_ticket_count
+                .saturating_sub(generator.preloaded as u128);
+            if spec.ticket_count as u128 > left {
+                return Err(format!(
+                    "Cannot generate {} new tickets: only {left} of the {} possible tickets haven't been issued yet",
+                    spec.ticket_count, generator.max_ticket_count
+                ));
+            }
+        }
+
+        Ok(generator)
+    }
+
+    /// Marks [`TicketSpec::issued_tickets`] as issued. Tickets this spec
+    /// can't produce are skipped, unless they could still be too close to
+    /// new ones under a minimum distance.
+    fn preload(&mut self, spec: &TicketSpec) {
+        for ticket in &spec.issued_tickets {
+            let body = match spec.check_length() {
+                0 => ticket,
+                check_length => ticket
+                    .char_indices()
+                    .nth_back(check_length - 1)
+                    .map_or("", |(start, _)| &ticket[..start]),
+            };
+
+            let key = self.layout.key_of(body);
+            if key.is_none() && !matches!(self.issued, IssuedSet::Distance(_)) {
+                continue;
+            }
+            let key = key.unwrap_or_default();
+
+            match &mut self.shards {
+                Some(shards) => {
+                    if shards.preload(ticket, key) {
+                        self.preloaded += 1;
+                    }
+                }
+                None => {
+                    if self.issued.is_available(ticket, key) {
+                        self.issued.insert(ticket, key);
+                        self.preloaded += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R> Generator<R> {
+    /// The seed this batch can be regenerated from, unless it was built
+    /// with [`Generator::with_rng`].
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// How many candidates the blocklist or
+    /// [`TicketSpec::spreadsheet_safe`] has rejected so far.
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    /// How many distinct earlier tickets new ones are kept away from.
+    pub fn preloaded(&self) -> usize {
+        self.preloaded
+    }
+
+    /// The permuted index the next ticket will be decoded from, or `None`
+    /// outside [`GenerationMode::Permuted`]. Once the batch is done this is
+    /// where the next batch with the same seed should start.
+    pub fn next_index(&self) -> Option<u128> {
+        match &self.layout {
+            Layout::Permuted(counter) => Some(counter.next_index()),
+            _ => None,
+        }
+    }
+}
+
+impl<R: RngCore> Generator<R> {
+    /// Like [`Iterator::next`], but writes the ticket into `buf` in place of
+    /// its contents. Reusing one buffer for a whole batch means no ticket
+    /// needs an allocation of its own.
+    pub fn next_into(&mut self, buf: &mut String) -> Option<Result<(), String>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let budget = attempt_budget(self.max_ticket_count, self.taken);
+        let key = match &mut self.shards {
+            Some(shards) => shards.next_into(
+                self.check.as_ref(),
+                self.blocklist.as_ref(),
+                &mut self.rejected,
+                budget,
+                self.remaining + 1,
+                buf,
+            ),
+            None => self.gen_token(budget, buf),
+        };
+        let key = match key {
+            Ok(key) => key,
+            Err(err) => return Some(Err(self.explain(err))),
+        };
+        if self.tracks_issued {
+            self.issued.insert(buf, key);
+        }
+        self.taken += 1;
+
+        Some(Ok(()))
+    }
+
+    /// Writes the next candidate that hasn't been issued into `buf` and
+    /// returns its key, giving up after `budget` candidates.
+    fn gen_token(&mut self, budget: usize, buf: &mut String) -> Result<u128, String> {
+        for _ in 0..budget {
+            buf.clear();
+            let candidate = draw(
+                &mut self.rng,
+                &mut self.layout,
+                self.check.as_ref(),
+                self.blocklist.as_ref(),
+                &mut self.scratch,
+                buf,
+            )?;
+            let Some(key) = candidate else {
+                self.rejected += 1;
+                continue;
+            };
+
+            if self.issued.is_available(buf, key) {
+                return Ok(key);
+            }
+        }
+
+        Err(format!(
+            "Could not find an unused ticket after {budget} attempts"
+        ))
+    }
+
+    /// Adds why no unused ticket could be found to `err`.
+    fn explain(&self, err: String) -> String {
+        if matches!(self.issued, IssuedSet::Distance(_)) {
+            format!(
+                "{err}. Fewer tickets than asked for may fit at the minimum distance, try a smaller count or distance"
+            )
+        } else if self.blocklist.is_some() && self.rejected > 0 {
+            format!(
+                "{err}. {} candidates were rejected, so the blocklist or spreadsheet-safe mode may leave fewer than the {} possible tickets",
+                self.rejected, self.max_ticket_count
+            )
+        } else {
+            format!(
+                "{err}. {} of the {} possible tickets were already used",
+                self.taken, self.max_ticket_count
+            )
+        }
+    }
+}
+
+impl<R: RngCore> Iterator for Generator<R> {
+    type Item = Result<String, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = String::new();
+        self.next_into(&mut buf).map(|result| result.map(|()| buf))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+/// How the random part of a ticket is drawn.
+#[derive(Debug, Clone)]
+pub(crate) enum Layout {
+    /// Each position draws independently from its own characters.
+    Positions(Vec<Position>),
+    /// Positions draw from the selected classes within their limits.
+    Classes(ClassSampler),
+    /// Tickets are decoded from a permuted counter instead of being drawn.
+    Permuted(Counter),
+}
+
+impl Layout {
+    /// Appends the next ticket body to `buf` and returns its key, the index
+    /// of the body among [`Layout::key_space`] possible ones. Keys wrap
+    /// around when the key space doesn't fit in a `u128`.
+    fn fill(&mut self, rng: &mut impl RngCore, buf: &mut String) -> Result<u128, String> {
+        match self {
+            Layout::Positions(positions) => {
+                let mut key = 0u128;
+                for position in positions {
+                    let index = uniform_index(rng, position.len());
+                    buf.push(position[index]);
+                    key = key
+                        .wrapping_mul(position.len() as u128)
+                        .wrapping_add(index as u128);
+                }
+                Ok(key)
+            }
+            Layout::Classes(sampler) => Ok(sampler.fill(rng, buf)),
+            Layout::Permuted(counter) => counter.fill(buf),
+        }
+    }
+
+    /// How many distinct keys [`Layout::fill`] can return, or `None` if that
+    /// doesn't fit in a `u128`.
+    pub(crate) fn key_space(&self) -> Option<u128> {
+        match self {
+            Layout::Positions(positions) => positions.iter().try_fold(1u128, |count, position| {
+                count.checked_mul(position.len() as u128)
+            }),
+            Layout::Classes(sampler) => sampler.key_space(),
+            Layout::Permuted(counter) => key_space(counter.positions()),
+        }
+    }
+
+    /// The key [`Layout::fill`] would return for `body`, or `None` if it
+    /// can't produce `body` at all.
+    fn key_of(&self, body: &str) -> Option<u128> {
+        match self {
+            Layout::Positions(positions) => key_of(positions, body),
+            Layout::Classes(sampler) => sampler.key_of(body),
+            Layout::Permuted(counter) => key_of(counter.positions(), body),
+        }
+    }
+}
+
+fn key_space(positions: &[Position]) -> Option<u128> {
+    positions.iter().try_fold(1u128, |count, position| {
+        count.checked_mul(position.len() as u128)
+    })
+}
+
+/// Mixed-radix index of `body` with one digit per position, the first being
+/// the most significant.
+fn key_of(positions: &[Position], body: &str) -> Option<u128> {
+    let mut chars = body.chars();
+    let mut key = 0u128;
+    for position in positions {
+        let c = chars.next()?;
+        let digit = position.iter().position(|&member| member == c)?;
+        key = key
+            .wrapping_mul(position.len() as u128)
+            .wrapping_add(digit as u128);
+    }
+
+    chars.next().is_none().then_some(key)
+}
+
+#[derive(Debug)]
+pub(crate) struct CheckCharacter {
+    algorithm: CheckAlgorithm,
+    alphabet: Vec<char>,
+    literals: String,
+}
+
+/// Buffers reused for every candidate, so checking one doesn't allocate.
+#[derive(Debug, Default)]
+pub(crate) struct Scratch {
+    /// Alphabet indices of the body, for the check characters.
+    values: Vec<usize>,
+    /// The candidate folded for the blocklist, and one window of it.
+    folded: Vec<char>,
+    window: String,
+}
+
+/// The fewest random candidates drawn before giving up on finding one that
+/// hasn't been issued yet.
+const MIN_ATTEMPTS: usize = 10_000;
+
+/// How many candidates to draw before giving up on an unused ticket when
+/// `taken` of `max_ticket_count` possible tickets are used. That's 40 times
+/// the draws a free ticket takes on average, so a batch that fits gives up
+/// with odds of about e^-40 per ticket, even when it fills every ticket.
+pub(crate) fn attempt_budget(max_ticket_count: u128, taken: u128) -> usize {
+    let free = max_ticket_count.saturating_sub(taken);
+    if free == 0 {
+        return MIN_ATTEMPTS;
+    }
+
+    let budget = max_ticket_count.div_ceil(free).saturating_mul(40);
+    usize::try_from(budget).map_or(usize::MAX, |budget| budget.max(MIN_ATTEMPTS))
+}
+
+/// Appends one candidate and its check character to `buf` and returns its
+/// key, or `None` if the blocklist rejected it.
+pub(crate) fn draw(
+    rng: &mut impl RngCore,
+    layout: &mut Layout,
+    check: Option<&CheckCharacter>,
+    blocklist: Option<&Blocklist>,
+    scratch: &mut Scratch,
+    buf: &mut String,
+) -> Result<Option<u128>, String> {
+    let key = layout.fill(rng, buf)?;
+
+    if let Some(check) = check {
+        check.algorithm.push_check_chars(
+            buf,
+            &check.alphabet,
+            &check.literals,
+            &mut scratch.values,
+        )?;
+    }
+
+    if blocklist.is_some_and(|blocklist| {
+        blocklist.is_blocked(buf, &mut scratch.folded, &mut scratch.window)
+    }) {
+        return Ok(None);
+    }
+
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seeded batches must never change, or old batches can't be regenerated.
+    #[test]
+    fn seeded_output_is_stable() {
+        let spec = TicketSpec {
+            capital_letters: true,
+            numbers: true,
+            ticket_length: 8,
+            ticket_count: 3,
+            seed: Some(42),
+            ..Default::default()
+        };
+
+        let tickets: Vec<String> = Generator::new(&spec)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tickets, ["DOS2924P", "0TRRG6RH", "YFV3Y808"]);
+    }
+
+    #[test]
+    fn check_characters_skip_template_literals() {
+        let spec = TicketSpec {
+            template: "EVT-####".to_owned(),
+            check_algorithm: Some(CheckAlgorithm::Iso7064Mod97_10),
+            ticket_count: 20,
+            seed: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(spec.literal_chars(), "EVT-");
+
+        for ticket in Generator::new(&spec).unwrap() {
+            let ticket = ticket.unwrap();
+            assert_eq!(ticket.len(), 10);
+            assert!(spec.check_ticket(&ticket), "{ticket}");
+            assert!(!spec.check_ticket(&ticket.replacen("EVT", "EXT", 1)));
+        }
+    }
+
+    #[test]
+    fn permuted_batches_continue_without_repeats() {
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 2,
+            ticket_count: 60,
+            seed: Some(7),
+            mode: GenerationMode::Permuted { start: 0 },
+            ..Default::default()
+        };
+        let mut first = Generator::new(&spec).unwrap();
+        let mut tickets: Vec<String> = first.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(first.next_index(), Some(60));
+
+        let rest = TicketSpec {
+            ticket_count: 40,
+            mode: GenerationMode::Permuted { start: 60 },
+            ..spec
+        };
+        for ticket in Generator::new(&rest).unwrap() {
+            tickets.push(ticket.unwrap());
+        }
+
+        tickets.sort_unstable();
+        let expected: Vec<String> = (0..100).map(|i| format!("{i:02}")).collect();
+        assert_eq!(tickets, expected);
+    }
+
+    #[test]
+    fn random_batches_can_use_every_ticket() {
+        for seed in 0..4 {
+            let spec = TicketSpec {
+                numbers: true,
+                ticket_length: 4,
+                ticket_count: 10_000,
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let mut tickets: Vec<String> = Generator::new(&spec)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            tickets.sort_unstable();
+            tickets.dedup();
+            assert_eq!(tickets.len(), 10_000);
+        }
+
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 3,
+            ticket_count: 100,
+            min_distance: 2,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let err = Generator::new(&spec)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(err.contains("minimum distance"), "{err}");
+    }
+
+    #[test]
+    fn large_batches_keep_their_distance() {
+        let spec = TicketSpec {
+            capital_letters: true,
+            numbers: true,
+            ticket_length: 8,
+            ticket_count: 100_000,
+            min_distance: 3,
+            seed: Some(5),
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        let tickets: Vec<String> = Generator::new(&spec)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tickets.len(), 100_000);
+        assert!(start.elapsed().as_secs() < 60, "{:?}", start.elapsed());
+
+        // Each ticket is far enough from the ones before it, and changing one
+        // position of a ticket brings it too close.
+        let first = &tickets[0];
+        let near = format!(
+            "{}{}",
+            if first.starts_with('Z') { 'Y' } else { 'Z' },
+            &first[1..]
+        );
+        let mut issued = IssuedSet::new(3, 0, None);
+        for ticket in &tickets {
+            assert!(issued.is_available(ticket, 0), "{ticket}");
+            issued.insert(ticket, 0);
+        }
+        assert!(!issued.is_available(&near, 0));
+    }
+
+    #[test]
+    fn sharded_output_ignores_thread_count() {
+        let batch = |threads| -> Vec<String> {
+            let spec = TicketSpec {
+                numbers: true,
+                ticket_length: 5,
+                ticket_count: 50_000,
+                seed: Some(3),
+                mode: GenerationMode::Sharded { threads },
+                ..Default::default()
+            };
+            Generator::new(&spec)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+
+        let one = batch(1);
+        assert_eq!(one, batch(3));
+
+        let mut unique = one.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), one.len());
+    }
+}
@@ -0,0 +1,152 @@
+//! Salted ticket hashes, so a server can check codes without storing them.
+
+use std::{fmt, fmt::Write, str::FromStr};
+
+use argon2::{Argon2, PasswordHasher, password_hash::SaltString};
+use sha2::{Digest, Sha256};
+
+use crate::Column;
+
+/// How tickets are hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// SHA-256 of the salt followed by the ticket. Fast enough for any batch,
+    /// but only as hard to brute force as the tickets are long.
+    #[default]
+    Sha256,
+    /// Argon2id with its default cost. Much harder to brute force, but it
+    /// takes tens of milliseconds per ticket, so large batches take a while.
+    Argon2id,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 2] = [HashAlgorithm::Sha256, HashAlgorithm::Argon2id];
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashAlgorithm::Sha256 => "SHA-256",
+            HashAlgorithm::Argon2id => "Argon2id",
+        })
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" | "sha-256" => Ok(HashAlgorithm::Sha256),
+            "argon2id" | "argon2" => Ok(HashAlgorithm::Argon2id),
+            _ => Err(format!(
+                "Unknown hash algorithm {s}, expected sha256 or argon2id"
+            )),
+        }
+    }
+}
+
+/// A second file holding a hash of every ticket, written in the same pass as
+/// the batch and in the same [`OutputFormat`](crate::OutputFormat).
+///
+/// Every ticket in a batch is hashed with the same random salt, so a server
+/// can hash a presented code once and look it up. Hashes are PHC strings,
+/// `$sha256$SALT$HEX` where `HEX` is SHA-256 of `SALT` as written followed
+/// by the ticket, or `$argon2id$v=19$m=19456,t=2,p=1$SALT$HASH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashExport {
+    pub path: String,
+    pub algorithm: HashAlgorithm,
+    /// Keeps the ticket next to its hash. Otherwise the hash takes the
+    /// ticket's place and the file holds no plaintext codes.
+    pub with_tickets: bool,
+}
+
+impl HashExport {
+    /// The hash file's columns, given the batch's.
+    pub(crate) fn columns(&self, columns: &[Column]) -> Vec<Column> {
+        let mut hash_columns = Vec::with_capacity(columns.len() + 1);
+        for column in columns {
+            if *column != Column::Ticket {
+                hash_columns.push(column.clone());
+                continue;
+            }
+
+            if self.with_tickets {
+                hash_columns.push(Column::Ticket);
+            }
+            hash_columns.push(Column::TicketHash);
+        }
+
+        hash_columns
+    }
+}
+
+/// Hashes the tickets of one batch with its salt.
+pub(crate) struct TicketHasher {
+    algorithm: HashAlgorithm,
+    salt: SaltString,
+    argon2: Argon2<'static>,
+}
+
+impl TicketHasher {
+    /// A hasher with a fresh random salt.
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Result<Self, String> {
+        let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+            .map_err(|err| format!("Failed to make a salt: {err}"))?;
+
+        Ok(Self {
+            algorithm,
+            salt,
+            argon2: Argon2::default(),
+        })
+    }
+
+    /// Replaces the contents of `buf` with the hash of `ticket`.
+    pub(crate) fn hash_into(&self, ticket: &str, buf: &mut String) -> Result<(), String> {
+        buf.clear();
+        match self.algorithm {
+            HashAlgorithm::Sha256 => {
+                let digest = Sha256::new()
+                    .chain_update(self.salt.as_str())
+                    .chain_update(ticket)
+                    .finalize();
+                let _ = write!(buf, "$sha256${}$", self.salt.as_str());
+                for byte in digest {
+                    let _ = write!(buf, "{byte:02x}");
+                }
+            }
+            HashAlgorithm::Argon2id => {
+                let hash = self
+                    .argon2
+                    .hash_password(ticket.as_bytes(), &self.salt)
+                    .map_err(|err| format!("Failed to hash ticket: {err}"))?;
+                let _ = write!(buf, "{hash}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_hashes_can_be_recomputed_from_the_salt() {
+        let hasher = TicketHasher::new(HashAlgorithm::Sha256).unwrap();
+        let mut hash = String::new();
+        hasher.hash_into("AB12CD", &mut hash).unwrap();
+
+        let salt = hash.split('$').nth(2).unwrap();
+        let digest = Sha256::digest(format!("{salt}AB12CD"));
+        let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+        assert_eq!(hash, format!("$sha256${salt}${hex}"));
+
+        let mut other = String::new();
+        hasher.hash_into("AB12CE", &mut other).unwrap();
+        assert!(other.starts_with(&format!("$sha256${salt}$")));
+        assert_ne!(hash, other);
+    }
+}
@@ -0,0 +1,325 @@
+//! Tickets issued so far, and whether a new candidate is far enough from
+//! all of them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{BuildHasher, Hasher, RandomState},
+};
+
+use crate::{Column, OutputFormat};
+
+/// Reads the tickets of an earlier batch so a new one can avoid them. Files
+/// ending in `.txt` hold one ticket per line, followed by a tab and its hash
+/// in a hash file. Anything else is read as CSV: the `ticket` column when
+/// the first row names one, and otherwise the only column, since without a
+/// header the tickets can't be told from serial numbers or other metadata.
+/// Other output formats can't be read back. Empty tickets are skipped, but
+/// spaces around a ticket are kept, since a custom class may draw them.
+pub fn load_issued_tickets(file_path: &str) -> Result<Vec<String>, String> {
+    let format = OutputFormat::from_path(file_path).unwrap_or_default();
+    if !matches!(format, OutputFormat::Csv | OutputFormat::Text) {
+        return Err(format!(
+            "Can't load tickets from {file_path}, only CSV and text files can be read back"
+        ));
+    }
+
+    let contents = std::fs::read_to_string(file_path)
+        .map_err(|err| format!("Failed to read {file_path}: {err}"))?;
+    read_tickets(&format, &contents).map_err(|err| format!("Failed to read {file_path}: {err}"))
+}
+
+/// The tickets in `contents`, a CSV or text file.
+fn read_tickets(format: &OutputFormat, contents: &str) -> Result<Vec<String>, String> {
+    if *format == OutputFormat::Text {
+        return Ok(ticket_lines(contents)
+            .map(|line| line.split_once('\t').map_or(line, |(ticket, _)| ticket))
+            .filter(|ticket| !ticket.is_empty())
+            .map(str::to_owned)
+            .collect());
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+
+    let mut tickets = Vec::new();
+    let mut ticket_column = None;
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| err.to_string())?;
+        if i == 0
+            && let Some(column) = record
+                .iter()
+                .position(|field| field.trim() == Column::Ticket.header())
+        {
+            ticket_column = Some(column);
+            continue;
+        }
+        if ticket_column.is_none() && record.len() > 1 {
+            return Err(format!(
+                "line {} has {} columns but there's no header naming the {} column",
+                i + 1,
+                record.len(),
+                Column::Ticket.header()
+            ));
+        }
+
+        let ticket_column = ticket_column.unwrap_or_default();
+        let ticket = record.get(ticket_column).unwrap_or_default();
+        if !ticket.is_empty() {
+            tickets.push(ticket.to_owned());
+        }
+    }
+
+    Ok(tickets)
+}
+
+/// The tickets in a file with one ticket per line, skipping empty lines.
+/// Only the line ending is removed, so tickets keep any spaces they have.
+pub(crate) fn ticket_lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.is_empty())
+}
+
+/// Tracks issued tickets so candidates can be checked against them.
+///
+/// Plain uniqueness remembers each ticket as its key, the index of its body
+/// among every body the layout can produce, whenever those indices fit in an
+/// integer. That takes 8 or 16 bytes per ticket instead of a heap-allocated
+/// `String`, and the check character can be left out because it follows
+/// from the body.
+#[derive(Debug, Clone)]
+pub(crate) enum IssuedSet {
+    /// Keys of issued tickets, when every key fits in a `u64`.
+    Packed64(HashSet<u64>),
+    /// Keys of issued tickets, when every key fits in a `u128`.
+    Packed128(HashSet<u128>),
+    /// Candidates only have to differ from every issued ticket, which are
+    /// too long to pack.
+    Exact(HashSet<String>),
+    /// Candidates have to differ from every issued ticket in at least
+    /// `min_distance` positions.
+    Distance(SegmentIndex),
+}
+
+impl IssuedSet {
+    /// `min_distance` of 0 or 1 means plain uniqueness. `key_space` is the
+    /// number of distinct keys, or `None` if it doesn't fit in a `u128`.
+    pub(crate) fn new(min_distance: usize, capacity: usize, key_space: Option<u128>) -> Self {
+        if min_distance > 1 {
+            return IssuedSet::Distance(SegmentIndex::new(min_distance));
+        }
+
+        match key_space {
+            Some(key_space) if key_space <= u64::MAX as u128 => {
+                IssuedSet::Packed64(HashSet::with_capacity(capacity))
+            }
+            Some(_) => IssuedSet::Packed128(HashSet::with_capacity(capacity)),
+            None => IssuedSet::Exact(HashSet::with_capacity(capacity)),
+        }
+    }
+
+    /// `key` is only meaningful for the packed sets.
+    pub(crate) fn is_available(&self, ticket: &str, key: u128) -> bool {
+        match self {
+            IssuedSet::Packed64(set) => !set.contains(&(key as u64)),
+            IssuedSet::Packed128(set) => !set.contains(&key),
+            IssuedSet::Exact(set) => !set.contains(ticket),
+            IssuedSet::Distance(index) => !index.any_closer(ticket),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, ticket: &str, key: u128) {
+        match self {
+            IssuedSet::Packed64(set) => {
+                set.insert(key as u64);
+            }
+            IssuedSet::Packed128(set) => {
+                set.insert(key);
+            }
+            IssuedSet::Exact(set) => {
+                set.insert(ticket.to_owned());
+            }
+            IssuedSet::Distance(index) => index.insert(ticket.to_owned()),
+        }
+    }
+}
+
+/// Finds issued tickets closer than `min_distance` to a candidate by the
+/// pigeonhole principle. Position `p` of a ticket belongs to segment
+/// `p % min_distance`, so two tickets fewer than `min_distance` apart must
+/// agree on every position of at least one segment. Each segment maps the
+/// characters at its positions to the tickets that have them, and only
+/// tickets sharing a whole segment with the candidate are compared.
+///
+/// Interleaving the segments keeps a run of template literals from filling
+/// one segment on its own, which would put every ticket in one bucket.
+#[derive(Debug, Clone)]
+pub(crate) struct SegmentIndex {
+    tickets: Vec<String>,
+    /// Hash of a segment's characters to the indices of tickets with them.
+    /// Hashes that collide only cost an extra comparison.
+    segments: Vec<HashMap<u64, Vec<usize>>>,
+    hasher: RandomState,
+}
+
+impl SegmentIndex {
+    pub(crate) fn new(min_distance: usize) -> Self {
+        Self {
+            tickets: Vec::new(),
+            segments: vec![HashMap::new(); min_distance.max(1)],
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, ticket: String) {
+        let index = self.tickets.len();
+        for segment in 0..self.segments.len() {
+            let hash = self.segment_hash(&ticket, segment);
+            self.segments[segment].entry(hash).or_default().push(index);
+        }
+        self.tickets.push(ticket);
+    }
+
+    /// Whether any ticket in the index is closer than `min_distance` to
+    /// `ticket`.
+    pub(crate) fn any_closer(&self, ticket: &str) -> bool {
+        let min_distance = self.segments.len();
+        (0..min_distance).any(|segment| {
+            self.segments[segment]
+                .get(&self.segment_hash(ticket, segment))
+                .is_some_and(|matches| {
+                    matches
+                        .iter()
+                        .any(|&index| hamming_distance(&self.tickets[index], ticket) < min_distance)
+                })
+        })
+    }
+
+    fn segment_hash(&self, ticket: &str, segment: usize) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        for c in ticket.chars().skip(segment).step_by(self.segments.len()) {
+            hasher.write_u32(c as u32);
+        }
+        hasher.finish()
+    }
+}
+
+/// Number of positions at which two tickets differ. Tickets of different
+/// lengths also differ at every position the shorter one lacks.
+pub(crate) fn hamming_distance(a: &str, b: &str) -> usize {
+    let mut a = a.chars();
+    let mut b = b.chars();
+    let mut distance = 0;
+    loop {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) => distance += usize::from(x != y),
+            (Some(_), None) | (None, Some(_)) => distance += 1,
+            (None, None) => return distance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{columns::Row, format::RowWriter};
+
+    /// `tickets` as written in `format` with `columns`.
+    fn write(format: OutputFormat, columns: &[Column], header: bool, tickets: &[&str]) -> String {
+        let mut out = Vec::new();
+        let mut writer = RowWriter::new(&format, &mut out, columns, header, &[]).unwrap();
+        for (index, ticket) in tickets.iter().enumerate() {
+            let row = Row {
+                ticket,
+                hash: "9f86d081",
+                index,
+                created_at: "",
+            };
+            writer.write_row(columns, &row).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn only_the_ticket_column_is_loaded() {
+        let tickets = ["AB,1", "0012", "C\"D"];
+        let read = |format, contents: String| read_tickets(&format, &contents).unwrap();
+
+        // With a header the ticket column is found wherever it is, and
+        // without one it has to be the only column.
+        let columns = [
+            Column::Serial { start: 1 },
+            Column::Ticket,
+            Column::BatchId("B1".to_owned()),
+        ];
+        let csv = write(OutputFormat::Csv, &columns, true, &tickets);
+        assert_eq!(read(OutputFormat::Csv, csv), tickets);
+        let csv = write(OutputFormat::Csv, &[Column::Ticket], false, &tickets);
+        assert_eq!(read(OutputFormat::Csv, csv), tickets);
+
+        let columns = [Column::Ticket, Column::TicketHash];
+        let text = write(OutputFormat::Text, &columns, false, &tickets);
+        assert_eq!(read(OutputFormat::Text, text), tickets);
+
+        assert_eq!(
+            read(OutputFormat::Csv, "AB12\r\n\r\n A \nCD34".to_owned()),
+            ["AB12", " A ", "CD34"]
+        );
+        assert_eq!(
+            read(OutputFormat::Text, "AB12\r\n\n A \t9f86\nCD34\r".to_owned()),
+            ["AB12", " A ", "CD34"]
+        );
+        assert_eq!(
+            load_issued_tickets("batch.json").unwrap_err(),
+            "Can't load tickets from batch.json, only CSV and text files can be read back"
+        );
+    }
+
+    #[test]
+    fn columns_without_a_header_are_refused() {
+        // Serial numbers first would otherwise be taken for the tickets.
+        let columns = [Column::Serial { start: 1 }, Column::Ticket];
+        let csv = write(OutputFormat::Csv, &columns, false, &["0012", "0034"]);
+        assert_eq!(
+            read_tickets(&OutputFormat::Csv, &csv).unwrap_err(),
+            "line 1 has 2 columns but there's no header naming the ticket column"
+        );
+
+        let csv = "0012\n0034,x\n";
+        assert!(read_tickets(&OutputFormat::Csv, csv).is_err());
+    }
+
+    #[test]
+    fn hamming_distance_counts_differences() {
+        assert_eq!(hamming_distance("ABCD", "ABCD"), 0);
+        assert_eq!(hamming_distance("ABCD", "ABXD"), 1);
+        assert_eq!(hamming_distance("ABCD", "DCBA"), 4);
+        assert_eq!(hamming_distance("ABC", "ABCDE"), 2);
+    }
+
+    #[test]
+    fn segment_index_matches_brute_force() {
+        let tickets = [
+            "AAAA", "AAAB", "ABBB", "BBBB", "CCCC", "ACAC", "BABA", "AAA", "AAAAB",
+        ];
+        for min_distance in 1..=5 {
+            let mut index = SegmentIndex::new(min_distance);
+            for ticket in tickets {
+                index.insert(ticket.to_owned());
+            }
+
+            for candidate in ["AAAA", "CCCA", "DDDD", "ABAB", "CACA", "AA", "AAAAAA"] {
+                let expected = tickets
+                    .iter()
+                    .any(|ticket| hamming_distance(ticket, candidate) < min_distance);
+                assert_eq!(index.any_closer(candidate), expected, "{candidate}");
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Ticket generation shared by the desktop app and any other program that
+//! needs batches of unique random codes.
+//!
+//! Describe a batch with a [`TicketSpec`], then either pull tickets from a
+//! [`Generator`] or write the whole batch to disk in its [`OutputFormat`]
+//! with [`build_batch`], or with [`build_batch_with_progress`] to follow and
+//! cancel a long job.
+
+mod aztec;
+mod barcode;
+mod blocklist;
+mod charset;
+mod check;
+mod code128;
+mod columns;
+mod datamatrix;
+mod format;
+mod generator;
+mod hash;
+mod issued;
+mod limits;
+mod output;
+mod pdf417;
+mod permutation;
+mod progress;
+mod reed_solomon;
+mod rng;
+mod shard;
+mod spec;
+mod spreadsheet;
+mod stats;
+mod template;
+
+pub use barcode::{
+    BarcodeExport, BarcodeFileNames, BarcodeImageFormat, QrErrorCorrection, Symbology,
+};
+pub use blocklist::{BUILTIN_BLOCKLIST, load_wordlist};
+pub use charset::{
+    BUILTIN_CLASSES, CAPITALS, CONFUSABLES, CharacterClass, CustomClass, LOWERS, NUMBERS, SPECIALS,
+};
+pub use check::CheckAlgorithm;
+pub use columns::Column;
+pub use format::OutputFormat;
+pub use generator::Generator;
+pub use hash::{HashAlgorithm, HashExport};
+pub use issued::load_issued_tickets;
+pub use limits::ClassLimits;
+pub use output::{BatchSummary, build_batch, build_batch_with_progress};
+pub use progress::Progress;
+pub use rng::random_seed;
+pub use spec::{GenerationMode, TicketSpec};
+pub use stats::{BatchStats, StatsThresholds};
@@ -0,0 +1,528 @@
+//! Per-class minimum and maximum character counts.
+//!
+//! Tickets are sampled uniformly from every ticket that satisfies the
+//! limits: first the number of characters each class contributes is drawn,
+//! weighted by how many tickets have that split, then the classes are
+//! shuffled across positions and each position draws from its class. The
+//! weights are integers, so a seed gives the same tickets on every platform.
+
+use rand::RngCore;
+
+use crate::rng::{shuffle, uniform_below, uniform_index};
+
+/// How many characters of one class every ticket must contain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClassLimits {
+    pub min: usize,
+    /// No upper bound when `None`.
+    pub max: Option<usize>,
+}
+
+impl ClassLimits {
+    /// Whether these limits restrict tickets at all.
+    pub fn is_active(&self) -> bool {
+        self.min > 0 || self.max.is_some()
+    }
+}
+
+/// Samples ticket bodies that respect the class limits.
+#[derive(Debug, Clone)]
+pub(crate) struct ClassSampler {
+    classes: Vec<Vec<char>>,
+    /// Where each class starts in the merged character set.
+    offsets: Vec<usize>,
+    /// Inclusive `(min, max)` count for each class, with `max` capped at the
+    /// ticket length.
+    bounds: Vec<(usize, usize)>,
+    length: usize,
+    /// `ways[i][r]` is the number of ways to fill `r` positions using only
+    /// classes `i..`.
+    ways: Vec<Vec<Weight>>,
+    /// Scratch space for [`ClassSampler::fill`], so drawing a ticket doesn't
+    /// allocate.
+    labels: Vec<usize>,
+    terms: Vec<Weight>,
+}
+
+impl ClassSampler {
+    /// `classes` must not share characters, see
+    /// [`TicketSpec::selected_classes`](crate::TicketSpec).
+    pub(crate) fn new(
+        classes: Vec<(String, Vec<char>, ClassLimits)>,
+        length: usize,
+    ) -> Result<Self, String> {
+        let mut bounds = Vec::with_capacity(classes.len());
+        for (name, chars, limits) in &classes {
+            if let Some(max) = limits.max.filter(|max| *max < limits.min) {
+                return Err(format!(
+                    "{name} allows at most {max} characters but needs at least {}",
+                    limits.min
+                ));
+            }
+
+            if chars.is_empty() && limits.min > 0 {
+                return Err(format!(
+                    "{name} needs at least {} characters but has none left after exclusions",
+                    limits.min
+                ));
+            }
+
+            let max = if chars.is_empty() {
+                0
+            } else {
+                limits.max.unwrap_or(length).min(length)
+            };
+            bounds.push((limits.min, max));
+        }
+
+        let min_total = bounds
+            .iter()
+            .try_fold(0usize, |total, (min, _)| total.checked_add(*min));
+        match min_total {
+            Some(min_total) if min_total <= length => {}
+            Some(min_total) => {
+                return Err(format!(
+                    "Class minimums add up to {min_total}, more than the ticket length of {length}"
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "Class minimums add up to more than the ticket length of {length}"
+                ));
+            }
+        }
+
+        let classes: Vec<Vec<char>> = classes.into_iter().map(|(_, chars, _)| chars).collect();
+        let offsets = classes
+            .iter()
+            .scan(0, |offset, chars| {
+                let start = *offset;
+                *offset += chars.len();
+                Some(start)
+            })
+            .collect();
+
+        let mut sampler = Self {
+            classes,
+            offsets,
+            bounds,
+            length,
+            ways: Vec::new(),
+            labels: Vec::with_capacity(length),
+            terms: Vec::with_capacity(length + 1),
+        };
+
+        let mut next = vec![Weight::ZERO; length + 1];
+        next[0] = Weight::ONE;
+        let mut ways = vec![next.clone()];
+        for i in (0..sampler.classes.len()).rev() {
+            let current: Vec<Weight> = (0..=length)
+                .map(|remaining| {
+                    sampler
+                        .terms(i, remaining, &next)
+                        .fold(Weight::ZERO, |total, (_, term)| total.add(term))
+                })
+                .collect();
+            ways.push(current.clone());
+            next = current;
+        }
+        ways.reverse();
+        sampler.ways = ways;
+
+        if sampler.ways[0][length].mantissa == 0 {
+            return Err(format!(
+                "Class maximums leave no way to fill {length} characters"
+            ));
+        }
+
+        Ok(sampler)
+    }
+
+    /// The exact number of tickets that satisfy the limits, saturating at
+    /// `u128::MAX`.
+    pub(crate) fn max_ticket_count(&self) -> u128 {
+        let class_count = self.classes.len();
+        let mut ways = vec![vec![0u128; self.length + 1]; class_count + 1];
+        ways[class_count][0] = 1;
+
+        let mut binomials = vec![1u128];
+        for remaining in 0..=self.length {
+            if remaining > 0 {
+                let mut next_row = vec![1u128; remaining + 1];
+                for k in 1..remaining {
+                    next_row[k] = binomials[k - 1].saturating_add(binomials[k]);
+                }
+                binomials = next_row;
+            }
+
+            for i in (0..class_count).rev() {
+                let size = self.classes[i].len() as u128;
+                ways[i][remaining] = self.counts(i, remaining).fold(0u128, |total, count| {
+                    let arrangements = u32::try_from(count)
+                        .ok()
+                        .and_then(|count| size.checked_pow(count))
+                        .unwrap_or(u128::MAX);
+                    total.saturating_add(
+                        binomials[count]
+                            .saturating_mul(arrangements)
+                            .saturating_mul(ways[i + 1][remaining - count]),
+                    )
+                });
+            }
+        }
+
+        ways[0][self.length]
+    }
+
+    /// Base-2 log of [`ClassSampler::max_ticket_count`], without saturating.
+    pub(crate) fn log2_ticket_count(&self) -> f64 {
+        let ways = self.ways[0][self.length];
+        (ways.mantissa as f64).log2() + ways.shift as f64
+    }
+
+    /// How many tickets there would be without the limits, which is the
+    /// range of the keys [`ClassSampler::fill`] returns, or `None` if that
+    /// doesn't fit in a `u128`.
+    pub(crate) fn key_space(&self) -> Option<u128> {
+        let size: usize = self.classes.iter().map(Vec::len).sum();
+        (size as u128).checked_pow(u32::try_from(self.length).ok()?)
+    }
+
+    /// Appends a uniformly chosen ticket body to `buf` and returns its index
+    /// in base `|character set|`, wrapping if it overflows.
+    pub(crate) fn fill(&mut self, rng: &mut impl RngCore, buf: &mut String) -> u128 {
+        let size: usize = self.classes.iter().map(Vec::len).sum();
+        let mut labels = std::mem::take(&mut self.labels);
+        let mut terms = std::mem::take(&mut self.terms);
+        labels.clear();
+        let mut remaining = self.length;
+        for i in 0..self.classes.len() {
+            let first = self.counts(i, remaining).start().to_owned();
+            terms.clear();
+            terms.extend(
+                self.terms(i, remaining, &self.ways[i + 1])
+                    .map(|(_, term)| term),
+            );
+
+            let count = first + pick(rng, &terms);
+            labels.extend(std::iter::repeat_n(i, count));
+            remaining -= count;
+        }
+        self.terms = terms;
+
+        shuffle(rng, &mut labels);
+        let mut key = 0u128;
+        for &label in &labels {
+            let class = &self.classes[label];
+            let index = uniform_index(rng, class.len());
+            buf.push(class[index]);
+            key = key
+                .wrapping_mul(size as u128)
+                .wrapping_add((self.offsets[label] + index) as u128);
+        }
+        self.labels = labels;
+
+        key
+    }
+
+    /// The key [`ClassSampler::fill`] would return for `body`, or `None` if
+    /// `body` isn't made of the right number of characters from the classes.
+    pub(crate) fn key_of(&self, body: &str) -> Option<u128> {
+        let size: usize = self.classes.iter().map(Vec::len).sum();
+        let mut key = 0u128;
+        let mut length = 0;
+        for c in body.chars() {
+            let digit = self
+                .classes
+                .iter()
+                .flatten()
+                .position(|&member| member == c)?;
+            key = key.wrapping_mul(size as u128).wrapping_add(digit as u128);
+            length += 1;
+        }
+
+        (length == self.length).then_some(key)
+    }
+
+    /// The counts class `i` may contribute when `remaining` positions are left.
+    fn counts(&self, i: usize, remaining: usize) -> std::ops::RangeInclusive<usize> {
+        let (min, max) = self.bounds[i];
+        min..=max.min(remaining)
+    }
+
+    /// The number of ways class `i` can take each count of the `remaining`
+    /// positions, with `next` holding `ways` for the following class.
+    fn terms<'a>(
+        &self,
+        i: usize,
+        remaining: usize,
+        next: &'a [Weight],
+    ) -> impl Iterator<Item = (usize, Weight)> + 'a {
+        let counts = self.counts(i, remaining);
+        let size = self.classes[i].len() as u64;
+        // Binomial coefficient times size^count, stepped along from count 0.
+        let mut ways = Weight::ONE;
+        (0..=*counts.end()).filter_map(move |count| {
+            let term = ways.mul(next[remaining - count]);
+            ways = ways
+                .mul_div((remaining - count) as u64, count as u64 + 1)
+                .mul(Weight::new(size as u128, 0));
+            counts.contains(&count).then_some((count, term))
+        })
+    }
+}
+
+/// A number of tickets, kept to its 64 most significant bits as
+/// `mantissa * 2^shift`. Exact while it fits, unlike a float, and the same on
+/// every platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Weight {
+    mantissa: u64,
+    shift: i32,
+}
+
+impl Weight {
+    const ZERO: Self = Self {
+        mantissa: 0,
+        shift: 0,
+    };
+    const ONE: Self = Self {
+        mantissa: 1,
+        shift: 0,
+    };
+
+    /// `value * 2^shift`, dropping bits beyond the 64 most significant.
+    fn new(value: u128, shift: i32) -> Self {
+        let excess = (128 - value.leading_zeros()).saturating_sub(64);
+        Self {
+            mantissa: (value >> excess) as u64,
+            shift: shift + excess as i32,
+        }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.mantissa as u128 * other.mantissa as u128,
+            self.shift + other.shift,
+        )
+    }
+
+    /// `self * factor / divisor`, keeping 32 more bits through the division
+    /// so stepping along binomial coefficients stays exact while they fit.
+    fn mul_div(self, factor: u64, divisor: u64) -> Self {
+        let wide = (self.mantissa as u128 * factor as u128) << 32;
+        Self::new(wide / divisor as u128, self.shift - 32)
+    }
+
+    fn add(self, other: Self) -> Self {
+        let shift = aligned_shift(&[self, other]);
+        Self::new(self.at(shift) + other.at(shift), shift)
+    }
+
+    /// The mantissa at `shift`, which [`aligned_shift`] keeps at most 32
+    /// below this one's.
+    fn at(self, shift: i32) -> u128 {
+        if self.mantissa == 0 {
+            return 0;
+        }
+        match self.shift - shift {
+            up @ 0.. => (self.mantissa as u128) << up,
+            down @ -127..0 => self.mantissa as u128 >> -down,
+            _ => 0,
+        }
+    }
+}
+
+/// A shift every weight can be expressed at without overflowing a sum of up
+/// to 2^32 of them, exact when they're close enough in size.
+fn aligned_shift(weights: &[Weight]) -> i32 {
+    let nonzero = weights.iter().filter(|weight| weight.mantissa != 0);
+    let lowest = nonzero.clone().map(|weight| weight.shift).min();
+    let highest = nonzero.map(|weight| weight.shift).max();
+    match (lowest, highest) {
+        (Some(lowest), Some(highest)) => lowest.max(highest - 32),
+        _ => 0,
+    }
+}
+
+/// Draws an index into `weights` with probability proportional to its weight.
+fn pick(rng: &mut impl RngCore, weights: &[Weight]) -> usize {
+    let shift = aligned_shift(weights);
+    let total: u128 = weights.iter().map(|weight| weight.at(shift)).sum();
+    let mut target = uniform_below(rng, total);
+    for (i, weight) in weights.iter().enumerate() {
+        let weight = weight.at(shift);
+        if target < weight {
+            return i;
+        }
+        target -= weight;
+    }
+
+    unreachable!("the target is below the total")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::seeded;
+
+    /// Two letters that at least one character must come from, and three
+    /// digits.
+    fn sampler(letters: ClassLimits, length: usize) -> Result<ClassSampler, String> {
+        ClassSampler::new(
+            vec![
+                ("Letters".to_owned(), vec!['a', 'b'], letters),
+                (
+                    "Digits".to_owned(),
+                    vec!['0', '1', '2'],
+                    ClassLimits::default(),
+                ),
+            ],
+            length,
+        )
+    }
+
+    /// Every ticket of `length` with between `min` and `max` letters.
+    fn brute_force(min: usize, max: usize, length: usize) -> Vec<String> {
+        let chars = ['a', 'b', '0', '1', '2'];
+        let mut tickets = vec![String::new()];
+        for _ in 0..length {
+            tickets = tickets
+                .iter()
+                .flat_map(|ticket| chars.iter().map(move |c| format!("{ticket}{c}")))
+                .collect();
+        }
+        tickets.retain(|ticket| {
+            let letters = ticket.chars().filter(char::is_ascii_alphabetic).count();
+            (min..=max).contains(&letters)
+        });
+
+        tickets
+    }
+
+    #[test]
+    fn counts_every_ticket_within_the_limits() {
+        for (min, max) in [(0, Some(0)), (1, None), (1, Some(2)), (4, None)] {
+            let sampler = sampler(ClassLimits { min, max }, 5).unwrap();
+            let expected = brute_force(min, max.unwrap_or(5), 5).len();
+            assert_eq!(sampler.max_ticket_count(), expected as u128);
+            let log2 = (expected as f64).log2();
+            assert!((sampler.log2_ticket_count() - log2).abs() < 1e-9);
+        }
+
+        // 95^300 overflows, but the weights keep their leading bits.
+        let printable: Vec<char> = (' '..='~').collect();
+        let long = ClassSampler::new(
+            vec![(
+                "All".to_owned(),
+                printable,
+                ClassLimits { min: 1, max: None },
+            )],
+            300,
+        )
+        .unwrap();
+        assert_eq!(long.max_ticket_count(), u128::MAX);
+        assert!((long.log2_ticket_count() - 300.0 * 95f64.log2()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn samples_every_ticket_equally_often() {
+        let mut sampler = sampler(ClassLimits { min: 1, max: None }, 3).unwrap();
+        let tickets = brute_force(1, 3, 3);
+        assert_eq!(tickets.len(), 98);
+
+        let mut rng = seeded(11);
+        let mut seen = std::collections::HashMap::new();
+        let mut buf = String::new();
+        for _ in 0..tickets.len() * 1000 {
+            buf.clear();
+            sampler.fill(&mut rng, &mut buf);
+            *seen.entry(buf.clone()).or_insert(0) += 1;
+        }
+
+        assert_eq!(seen.len(), tickets.len());
+        for ticket in &tickets {
+            let count = seen[ticket];
+            assert!((800..1200).contains(&count), "{ticket} drawn {count} times");
+        }
+    }
+
+    #[test]
+    fn sampling_is_pinned_by_the_seed() {
+        let mut sampler = sampler(ClassLimits { min: 2, max: None }, 8).unwrap();
+        let mut rng = seeded(42);
+        let tickets: Vec<String> = (0..3)
+            .map(|_| {
+                let mut buf = String::new();
+                sampler.fill(&mut rng, &mut buf);
+                buf
+            })
+            .collect();
+        assert_eq!(tickets, ["202b1ab0", "0ab202ab", "baba22a0"]);
+    }
+
+    #[test]
+    fn impossible_limits_are_explained() {
+        let error = |letters, length| sampler(letters, length).unwrap_err();
+        assert_eq!(
+            error(
+                ClassLimits {
+                    min: 3,
+                    max: Some(2)
+                },
+                5
+            ),
+            "Letters allows at most 2 characters but needs at least 3"
+        );
+        assert_eq!(
+            error(ClassLimits { min: 6, max: None }, 5),
+            "Class minimums add up to 6, more than the ticket length of 5"
+        );
+        let huge = ClassLimits {
+            min: usize::MAX,
+            max: None,
+        };
+        assert_eq!(
+            ClassSampler::new(
+                vec![
+                    ("Numbers".to_owned(), vec!['0'], huge),
+                    (
+                        "Letters".to_owned(),
+                        vec!['a'],
+                        ClassLimits { min: 2, max: None }
+                    ),
+                ],
+                3,
+            )
+            .unwrap_err(),
+            "Class minimums add up to more than the ticket length of 3"
+        );
+        assert_eq!(
+            ClassSampler::new(
+                vec![(
+                    "Letters".to_owned(),
+                    Vec::new(),
+                    ClassLimits { min: 1, max: None },
+                )],
+                5,
+            )
+            .unwrap_err(),
+            "Letters needs at least 1 characters but has none left after exclusions"
+        );
+        assert_eq!(
+            ClassSampler::new(
+                vec![(
+                    "Letters".to_owned(),
+                    vec!['a'],
+                    ClassLimits {
+                        min: 0,
+                        max: Some(2)
+                    },
+                )],
+                5,
+            )
+            .unwrap_err(),
+            "Class maximums leave no way to fill 5 characters"
+        );
+    }
+}
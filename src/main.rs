@@ -1,14 +1,22 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use rand::seq::IndexedRandom;
-use std::{collections::HashSet, fmt::Write, fs::File, slice};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
 
 use egui_inbox::UiInbox;
-
-const CAPTIALS: &str = "ABCDEFGHIJKLMNOPQRSTUVQXYZ";
-const LOWERS: &str = "abcdefghijklmnopqrstuvqxyz";
-const NUMBERS: &str = "0123456789";
-const SPECIALS: &str = ",.;:\"'!%#";
+use ticket_gen::{
+    BarcodeExport, BarcodeFileNames, BarcodeImageFormat, BatchStats, CheckAlgorithm, Column,
+    CustomClass, GenerationMode, HashAlgorithm, HashExport, OutputFormat, Progress,
+    QrErrorCorrection, StatsThresholds, Symbology, TicketSpec, build_batch_with_progress,
+    load_issued_tickets, load_wordlist,
+};
+
+const TEMPLATE_HELP: &str = "Leave empty to use the ticket length.\n\
+    # digit, A capital letter, a lowercase letter, ? any selected character\n\
+    [A-Z0-9] one of the listed characters, {n} repeats the previous item\n\
+    \\ makes the next character literal, anything else is copied as is";
 
 fn main() -> eframe::Result {
     env_logger::init();
@@ -28,42 +36,62 @@ fn main() -> eframe::Result {
 
 #[derive(Debug, Default)]
 struct RandomizerApp {
-    capital_letters: bool,
-    lowercase_letters: bool,
-    numbers: bool,
-    specials: bool,
-    rejected_chars: String,
-    ticket_count: usize,
+    spec: TicketSpec,
     ticket_count_str: String,
-    ticket_length: usize,
     ticket_length_str: String,
+    seed_str: String,
+    start_index_str: String,
     file_path: Option<String>,
+    /// Tickets in the registry when it was selected.
+    registry_count: usize,
     is_processing: bool,
-    inbox: UiInbox<String>,
+    inbox: UiInbox<JobMessage>,
     last_thread_message: String,
+    progress: Option<Progress>,
+    cancel: Arc<AtomicBool>,
+    thresholds: StatsThresholds,
+    /// What the settings work out to, kept until they change.
+    figures: Option<Figures>,
 }
 
-impl RandomizerApp {
-    fn build_character_set(&self) -> String {
-        let mut buf = String::new();
-        if self.capital_letters {
-            buf.push_str(CAPTIALS);
-        }
+/// Figures shown for a spec, which take too long to work out every frame.
+#[derive(Debug)]
+struct Figures {
+    /// The spec they were worked out for, without its issued tickets.
+    spec: TicketSpec,
+    stats: BatchStats,
+    removed_confusables: String,
+}
 
-        if self.lowercase_letters {
-            buf.push_str(LOWERS);
-        }
-        if self.numbers {
-            buf.push_str(NUMBERS);
-        }
+/// What the worker thread reports back to the UI.
+#[derive(Debug)]
+enum JobMessage {
+    Progress(Progress),
+    /// The job is over, with the summary or error to show.
+    Finished(String),
+}
 
-        if self.specials {
-            buf.push_str(SPECIALS);
+impl RandomizerApp {
+    /// The figures for the current settings, worked out again only if they
+    /// changed since the last call.
+    fn figures(&mut self) -> &Figures {
+        // The issued tickets can run to millions and no figure depends on
+        // them, so they're left out of the comparison and the copy.
+        let issued_tickets = std::mem::take(&mut self.spec.issued_tickets);
+        if self
+            .figures
+            .as_ref()
+            .is_none_or(|figures| figures.spec != self.spec)
+        {
+            self.figures = Some(Figures {
+                spec: self.spec.clone(),
+                stats: BatchStats::new(&self.spec),
+                removed_confusables: self.spec.removed_confusables(),
+            });
         }
+        self.spec.issued_tickets = issued_tickets;
 
-        let rejected_chars: Vec<char> = self.rejected_chars.chars().collect();
-
-        buf.replace(&rejected_chars[..], "")
+        self.figures.as_ref().expect("set above")
     }
 
     fn start_processing(&mut self) {
@@ -75,155 +103,751 @@ impl RandomizerApp {
             return;
         }
 
-        let character_set = self.build_character_set();
-        if character_set.is_empty() {
-            return;
-        }
-
-        if self.ticket_length == 0 || self.ticket_count == 0 {
+        let tx = self.inbox.sender();
+        let file_path = self.file_path.clone().unwrap();
+        if let Err(err) = self
+            .spec
+            .validate()
+            .and_then(|()| self.spec.check_output_path(&file_path))
+        {
+            let _ = tx.send(JobMessage::Finished(err));
             return;
         }
 
         self.is_processing = true;
-        let tx = self.inbox.sender();
-        let file_path = self.file_path.clone().unwrap();
-        let token_count = self.ticket_count;
-        let ticket_length = self.ticket_length;
+        self.progress = None;
+        self.cancel = Arc::new(AtomicBool::new(false));
+        let spec = self.spec.clone();
+        let cancel = self.cancel.clone();
 
         std::thread::spawn(move || {
-            // TODO: We should do something here
-            let _ = match build_csv(character_set, file_path, token_count, ticket_length) {
-                Ok(()) => tx.send("Successfully wrote to CSV".to_owned()),
-                Err(str) => tx.send(str),
+            let result = build_batch_with_progress(&spec, &file_path, &cancel, |progress| {
+                let _ = tx.send(JobMessage::Progress(progress));
+            });
+            let _ = match result {
+                Ok(summary) => tx.send(JobMessage::Finished(summary.to_string())),
+                Err(str) => tx.send(JobMessage::Finished(str)),
             };
         });
     }
 }
 
-fn build_csv(
-    character_set: String,
-    file_path: String,
-    token_count: usize,
-    token_length: usize,
-) -> Result<(), String> {
-    let file = File::create(&file_path)
-        .map_err(|err| format!("Failed to create file {file_path}: {err}"))?;
-
-    let mut set: HashSet<String> = HashSet::new();
+impl eframe::App for RandomizerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Ticket Randomizer");
+                ui.checkbox(&mut self.spec.capital_letters, "Capital Letters (A-Z)");
+                ui.checkbox(&mut self.spec.lowercase_letters, "Lowercase Letters (a-z)");
+                ui.checkbox(&mut self.spec.numbers, "Number (0-9)");
+                ui.checkbox(&mut self.spec.specials, "Specials (,.;:\"'!%#)");
+
+                ui.collapsing("Custom Classes", |ui| {
+                    let mut removed = None;
+                    for (i, class) in self.spec.custom_classes.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut class.enabled, "");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut class.name)
+                                    .hint_text("Name")
+                                    .desired_width(80.0),
+                            );
+                            ui.add(
+                                egui::TextEdit::singleline(&mut class.chars)
+                                    .hint_text("Characters")
+                                    .desired_width(140.0),
+                            );
+                            if ui.button("Remove").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = removed {
+                        self.spec.custom_classes.remove(i);
+                    }
 
-    let character_set: Vec<char> = character_set.chars().collect();
-    let mut rng = rand::rng();
+                    if ui.button("Add class").clicked() {
+                        self.spec.custom_classes.push(CustomClass {
+                            enabled: true,
+                            ..Default::default()
+                        });
+                    }
+                });
+
+                // Limits on a class that was switched off or renamed can't be
+                // edited any more, and would make the spec invalid.
+                let selected_classes = self.spec.selected_classes();
+                self.spec
+                    .class_limits
+                    .retain(|name, _| selected_classes.iter().any(|(class, _)| class == name));
+
+                ui.collapsing("Class Limits", |ui| {
+                    for (name, _) in selected_classes {
+                        let limits = self.spec.class_limits.entry(name.clone()).or_default();
+                        ui.horizontal(|ui| {
+                            ui.label(&name);
+                            ui.label("min");
+                            ui.add(egui::DragValue::new(&mut limits.min));
+
+                            let mut has_max = limits.max.is_some();
+                            if ui.checkbox(&mut has_max, "max").changed() {
+                                limits.max = has_max.then_some(limits.min);
+                            }
+                            if let Some(max) = &mut limits.max {
+                                ui.add(egui::DragValue::new(max));
+                            }
+                        });
+                    }
+                });
+
+                ui.checkbox(
+                    &mut self.spec.human_friendly,
+                    "Human-friendly (drop look-alike characters)",
+                );
+                let removed_confusables = &self.figures().removed_confusables;
+                if !removed_confusables.is_empty() {
+                    ui.label(format!("Removed look-alikes: {removed_confusables}"));
+                }
 
-    let mut csv_writer = csv::WriterBuilder::new().from_writer(file);
+                ui.horizontal(|ui| {
+                    let template_label = ui.label("Template: ");
+                    ui.text_edit_singleline(&mut self.spec.template)
+                        .labelled_by(template_label.id)
+                        .on_hover_text(TEMPLATE_HELP);
+                });
+
+                egui::ComboBox::from_label("Check Characters")
+                    .selected_text(match self.spec.check_algorithm {
+                        Some(algorithm) => algorithm.to_string(),
+                        None => "None".to_owned(),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.spec.check_algorithm, None, "None");
+                        for algorithm in CheckAlgorithm::ALL {
+                            ui.selectable_value(
+                                &mut self.spec.check_algorithm,
+                                Some(algorithm),
+                                algorithm.to_string(),
+                            );
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    let rejected_chars_label = ui.label("Excluded Characters: ");
+                    ui.text_edit_singleline(&mut self.spec.rejected_chars)
+                        .labelled_by(rejected_chars_label.id);
+                });
+
+                ui.checkbox(&mut self.spec.builtin_blocklist, "Reject offensive words");
+                ui.horizontal(|ui| {
+                    if ui.button("Load wordlist...").clicked() {
+                        let file_dialog = rfd::FileDialog::new().add_filter("txt", &["txt"]);
+
+                        if let Some(path) = file_dialog.pick_file() {
+                            match load_wordlist(&path.display().to_string()) {
+                                Ok(words) => self.spec.blocked_words.extend(words),
+                                Err(err) => self.last_thread_message = err,
+                            }
+                        }
+                    }
 
-    for _ in 0..token_count {
-        let new_token = gen_token(&mut rng, &character_set, &set, token_length)?;
-        csv_writer
-            .write_record(slice::from_ref(&new_token))
-            .map_err(|err| format!("Failed to write to file: {err}"))?;
+                    if !self.spec.blocked_words.is_empty() {
+                        ui.label(format!("{} blocked words", self.spec.blocked_words.len()));
+                        if ui.button("Clear").clicked() {
+                            self.spec.blocked_words.clear();
+                        }
+                    }
+                });
+                ui.checkbox(&mut self.spec.spreadsheet_safe, "Spreadsheet-safe")
+                    .on_hover_text(
+                        "Redraw tickets a spreadsheet would read as a formula, number, date, \
+                         boolean or error, like =A1, -12, 000123, 1E10, 12/31, Mar-1 or #N/A",
+                    );
+
+                ui.horizontal(|ui| {
+                    let count_label = ui.label("Ticket Count: ");
+                    if ui
+                        .text_edit_singleline(&mut self.ticket_count_str)
+                        .labelled_by(count_label.id)
+                        .lost_focus()
+                    {
+                        if let Ok(parsed) = self.ticket_count_str.parse::<usize>() {
+                            self.spec.ticket_count = parsed;
+                        } else {
+                            self.ticket_count_str = self.spec.ticket_count.to_string();
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Minimum Distance: ")
+                        .on_hover_text("Positions (Hamming) in which every pair of tickets must differ");
+                    ui.add(egui::DragValue::new(&mut self.spec.min_distance).range(0..=64));
+                });
+
+                let uses_template = !self.spec.template.is_empty();
+                ui.add_enabled_ui(!uses_template, |ui| {
+                    ui.horizontal(|ui| {
+                        let length_label = ui.label("Ticket Length: ");
+                        if ui
+                            .text_edit_singleline(&mut self.ticket_length_str)
+                            .labelled_by(length_label.id)
+                            .lost_focus()
+                        {
+                            if let Ok(parsed) = self.ticket_length_str.parse::<usize>() {
+                                self.spec.ticket_length = parsed;
+                            } else {
+                                self.ticket_length_str = self.spec.ticket_length.to_string();
+                            }
+                        }
+                    });
+                });
+
+                ui.horizontal(|ui| {
+                    let seed_label = ui.label("Seed: ");
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut self.seed_str).hint_text("random"))
+                        .labelled_by(seed_label.id)
+                        .lost_focus()
+                    {
+                        if self.seed_str.trim().is_empty() {
+                            self.spec.seed = None;
+                        } else if let Ok(parsed) = self.seed_str.trim().parse::<u64>() {
+                            self.spec.seed = Some(parsed);
+                        } else {
+                            self.seed_str = self
+                                .spec
+                                .seed
+                                .map(|seed| seed.to_string())
+                                .unwrap_or_default();
+                        }
+                    }
+                });
+
+                let permuted = GenerationMode::Permuted {
+                    start: self.start_index_str.trim().parse().unwrap_or_default(),
+                };
+                egui::ComboBox::from_label("Generation")
+                    .selected_text(self.spec.mode.to_string())
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.spec.mode, GenerationMode::Random, "Random")
+                            .on_hover_text("Draw tickets at random on one thread");
+                        if ui
+                            .selectable_label(
+                                matches!(self.spec.mode, GenerationMode::Permuted { .. }),
+                                "Permuted",
+                            )
+                            .on_hover_text(
+                                "Decode tickets from a permutation of every possible ticket keyed by the seed, \
+                                 so huge batches stay unique without being kept in memory",
+                            )
+                            .clicked()
+                        {
+                            self.spec.mode = permuted;
+                        }
+                        if ui
+                            .selectable_label(
+                                matches!(self.spec.mode, GenerationMode::Sharded { .. }),
+                                "Sharded",
+                            )
+                            .on_hover_text(
+                                "Draw tickets at random on several threads. \
+                                 A seed gives the same batch for any thread count",
+                            )
+                            .clicked()
+                        {
+                            self.spec.mode = GenerationMode::Sharded { threads: 0 };
+                        }
+                    });
+                if let GenerationMode::Permuted { start } = &mut self.spec.mode {
+                    ui.horizontal(|ui| {
+                        let start_label = ui.label("Start Index: ");
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut self.start_index_str).hint_text("0"))
+                            .labelled_by(start_label.id)
+                            .on_hover_text("Use the end of the previous batch's index range to continue it")
+                            .lost_focus()
+                        {
+                            if self.start_index_str.trim().is_empty() {
+                                *start = 0;
+                            } else if let Ok(parsed) = self.start_index_str.trim().parse::<u128>() {
+                                *start = parsed;
+                            } else {
+                                self.start_index_str = start.to_string();
+                            }
+                        }
+                    });
+                }
+                if let GenerationMode::Sharded { threads } = &mut self.spec.mode {
+                    ui.horizontal(|ui| {
+                        ui.label("Threads: ")
+                            .on_hover_text("0 uses one thread per core");
+                        ui.add(egui::DragValue::new(threads).range(0..=GenerationMode::MAX_THREADS));
+                    });
+                }
 
-        set.insert(new_token);
-    }
+                ui.collapsing("Earlier Batches", |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Load batches...").clicked() {
+                            let file_dialog = rfd::FileDialog::new()
+                                .add_filter("csv", &["csv"])
+                                .add_filter("txt", &["txt"]);
+
+                            for path in file_dialog.pick_files().unwrap_or_default() {
+                                match load_issued_tickets(&path.display().to_string()) {
+                                    Ok(tickets) => self.spec.issued_tickets.extend(tickets),
+                                    Err(err) => self.last_thread_message = err,
+                                }
+                            }
+                        }
+
+                        if !self.spec.issued_tickets.is_empty() {
+                            ui.label(format!(
+                                "{} earlier tickets loaded",
+                                self.spec.issued_tickets.len()
+                            ));
+                            if ui.button("Clear").clicked() {
+                                self.spec.issued_tickets.clear();
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Select registry...")
+                            .on_hover_text(
+                                "Text file of every ticket issued so far. \
+                                 Its tickets are avoided and each batch is added to it",
+                            )
+                            .clicked()
+                        {
+                            let file_dialog = rfd::FileDialog::new().add_filter("txt", &["txt"]);
+
+                            if let Some(path) = file_dialog.save_file() {
+                                let path = path.display().to_string();
+                                self.registry_count = load_issued_tickets(&path)
+                                    .map(|tickets| tickets.len())
+                                    .unwrap_or_default();
+                                self.spec.registry = Some(path);
+                            }
+                        }
+
+                        if let Some(registry) = &self.spec.registry {
+                            ui.label(format!("{registry} ({} tickets)", self.registry_count));
+                            if ui.button("Clear").clicked() {
+                                self.spec.registry = None;
+                            }
+                        }
+                    });
+                });
+
+                ui.collapsing("Columns", |ui| {
+                    ui.checkbox(&mut self.spec.header, "Header row");
+
+                    if self.spec.columns.is_empty() {
+                        self.spec.columns.push(Column::Ticket);
+                    }
+                    let mut moved_up = None;
+                    let mut removed = None;
+                    for (i, column) in self.spec.columns.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            match column {
+                                Column::Ticket => {
+                                    ui.label("Ticket");
+                                }
+                                Column::Serial { start } => {
+                                    ui.label("Serial from");
+                                    ui.add(egui::DragValue::new(start));
+                                }
+                                Column::BatchId(value) => {
+                                    ui.label("Batch ID");
+                                    ui.add(egui::TextEdit::singleline(value).desired_width(120.0));
+                                }
+                                Column::CreatedAt => {
+                                    ui.label("Creation time");
+                                }
+                                Column::TicketHash => {
+                                    ui.label("Ticket hash");
+                                }
+                                Column::TicketType(value) => {
+                                    ui.label("Ticket type");
+                                    ui.add(egui::TextEdit::singleline(value).desired_width(120.0));
+                                }
+                                Column::Constant { header, value } => {
+                                    ui.add(
+                                        egui::TextEdit::singleline(header)
+                                            .hint_text("Header")
+                                            .desired_width(80.0),
+                                    );
+                                    ui.add(
+                                        egui::TextEdit::singleline(value)
+                                            .hint_text("Value")
+                                            .desired_width(120.0),
+                                    );
+                                }
+                            }
+
+                            if i > 0 && ui.button("Up").clicked() {
+                                moved_up = Some(i);
+                            }
+                            if *column != Column::Ticket && ui.button("Remove").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = moved_up {
+                        self.spec.columns.swap(i - 1, i);
+                    }
+                    if let Some(i) = removed {
+                        self.spec.columns.remove(i);
+                    }
 
-    Ok(())
-}
+                    ui.menu_button("Add column", |ui| {
+                        let added = [
+                            ("Serial number", Column::Serial { start: 1 }),
+                            ("Batch ID", Column::BatchId(String::new())),
+                            ("Creation time", Column::CreatedAt),
+                            ("Ticket type", Column::TicketType(String::new())),
+                            (
+                                "Constant",
+                                Column::Constant {
+                                    header: String::new(),
+                                    value: String::new(),
+                                },
+                            ),
+                        ];
+                        for (label, column) in added {
+                            if ui.button(label).clicked() {
+                                self.spec.columns.push(column);
+                                ui.close();
+                            }
+                        }
+                    });
+                });
+
+                ui.collapsing("Ticket Hashes", |ui| {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Select hash file...")
+                            .on_hover_text(
+                                "Also write a salted hash of every ticket to this file, \
+                                 in the same format, for a server to check tickets against",
+                            )
+                            .clicked()
+                        {
+                            let extension = self.spec.format.extension();
+                            let file_dialog = rfd::FileDialog::new()
+                                .add_filter(self.spec.format.to_string(), &[extension]);
+
+                            if let Some(path) = file_dialog.save_file() {
+                                let path = path.display().to_string();
+                                match &mut self.spec.hashes {
+                                    Some(hashes) => hashes.path = path,
+                                    None => {
+                                        self.spec.hashes = Some(HashExport {
+                                            path,
+                                            algorithm: HashAlgorithm::default(),
+                                            with_tickets: false,
+                                        });
+                                    }
+                                }
+                            }
+                        }
+
+                        if let Some(hashes) = &self.spec.hashes {
+                            ui.label(&hashes.path);
+                            if ui.button("Clear").clicked() {
+                                self.spec.hashes = None;
+                            }
+                        }
+                    });
+
+                    if let Some(hashes) = &mut self.spec.hashes {
+                        ui.horizontal(|ui| {
+                            ui.label("Algorithm: ");
+                            egui::ComboBox::from_id_salt("hash_algorithm")
+                                .selected_text(hashes.algorithm.to_string())
+                                .show_ui(ui, |ui| {
+                                    for algorithm in HashAlgorithm::ALL {
+                                        ui.selectable_value(
+                                            &mut hashes.algorithm,
+                                            algorithm,
+                                            algorithm.to_string(),
+                                        );
+                                    }
+                                });
+                        });
+                        ui.checkbox(&mut hashes.with_tickets, "Keep tickets next to their hashes");
+                    }
+                });
+
+                ui.collapsing("Barcodes", |ui| {
+                    ui.horizontal(|ui| {
+                        let mut path = None;
+                        if ui.button("Select folder...").clicked() {
+                            path = rfd::FileDialog::new().pick_folder();
+                        }
+                        if ui.button("Select ZIP...").clicked() {
+                            path = rfd::FileDialog::new()
+                                .add_filter("zip", &["zip"])
+                                .save_file();
+                        }
+                        if let Some(path) = path {
+                            let path = path.display().to_string();
+                            match &mut self.spec.barcodes {
+                                Some(barcodes) => barcodes.path = path,
+                                None => {
+                                    self.spec.barcodes = Some(BarcodeExport {
+                                        path,
+                                        ..Default::default()
+                                    });
+                                }
+                            }
+                        }
+
+                        if let Some(barcodes) = &self.spec.barcodes {
+                            ui.label(&barcodes.path);
+                            if ui.button("Clear").clicked() {
+                                self.spec.barcodes = None;
+                            }
+                        }
+                    });
+
+                    if let Some(barcodes) = &mut self.spec.barcodes {
+                        ui.horizontal(|ui| {
+                            ui.label("Symbology: ");
+                            let previous = barcodes.symbology;
+                            egui::ComboBox::from_id_salt("symbology")
+                                .selected_text(barcodes.symbology.to_string())
+                                .show_ui(ui, |ui| {
+                                    for symbology in Symbology::ALL {
+                                        ui.selectable_value(
+                                            &mut barcodes.symbology,
+                                            symbology,
+                                            symbology.to_string(),
+                                        );
+                                    }
+                                });
+                            if barcodes.symbology != previous {
+                                barcodes.quiet_zone = barcodes.symbology.quiet_zone();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Image: ");
+                            egui::ComboBox::from_id_salt("barcode_image_format")
+                                .selected_text(barcodes.image_format.to_string())
+                                .show_ui(ui, |ui| {
+                                    for image_format in BarcodeImageFormat::ALL {
+                                        ui.selectable_value(
+                                            &mut barcodes.image_format,
+                                            image_format,
+                                            image_format.to_string(),
+                                        );
+                                    }
+                                });
+                        });
+                        if barcodes.symbology == Symbology::Qr {
+                            ui.horizontal(|ui| {
+                                ui.label("Error correction: ");
+                                egui::ComboBox::from_id_salt("qr_error_correction")
+                                    .selected_text(barcodes.error_correction.to_string())
+                                    .show_ui(ui, |ui| {
+                                        for error_correction in QrErrorCorrection::ALL {
+                                            ui.selectable_value(
+                                                &mut barcodes.error_correction,
+                                                error_correction,
+                                                error_correction.to_string(),
+                                            );
+                                        }
+                                    });
+                            });
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Module size: ");
+                            ui.add(
+                                egui::DragValue::new(&mut barcodes.module_size)
+                                    .range(1..=64)
+                                    .suffix(" px"),
+                            );
+                            ui.label("Quiet zone: ");
+                            ui.add(
+                                egui::DragValue::new(&mut barcodes.quiet_zone)
+                                    .range(barcodes.symbology.quiet_zone()..=16)
+                                    .suffix(" modules"),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("File names: ");
+                            egui::ComboBox::from_id_salt("barcode_file_names")
+                                .selected_text(barcodes.file_names.to_string())
+                                .show_ui(ui, |ui| {
+                                    for file_names in BarcodeFileNames::ALL {
+                                        ui.selectable_value(
+                                            &mut barcodes.file_names,
+                                            file_names,
+                                            file_names.to_string(),
+                                        );
+                                    }
+                                });
+                        });
+                    }
 
-fn gen_token(
-    rng: &mut impl rand::Rng,
-    character_set: &[char],
-    already_generated: &HashSet<String>,
-    token_length: usize,
-) -> Result<String, String> {
-    let mut buf = String::with_capacity(token_length);
-    for _ in 0..token_length {
-        let char = character_set.choose(rng).unwrap();
-        buf.write_char(*char)
-            .map_err(|_e| "Failed to write to string")?;
-    }
+                    if let Err(err) = self.spec.check_barcodes() {
+                        ui.colored_label(ui.visuals().error_fg_color, err);
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Format: ");
+                    let previous = self.spec.format.clone();
+                    egui::ComboBox::from_id_salt("format")
+                        .selected_text(self.spec.format.to_string())
+                        .show_ui(ui, |ui| {
+                            for format in OutputFormat::all() {
+                                let selected = std::mem::discriminant(&self.spec.format)
+                                    == std::mem::discriminant(&format);
+                                let text = format.to_string();
+                                if ui.selectable_label(selected, text).clicked() && !selected {
+                                    self.spec.format = format;
+                                }
+                            }
+                        });
+                    if self.spec.format != previous
+                        && let Some(file_path) = &mut self.file_path
+                    {
+                        *file_path = std::path::Path::new(file_path)
+                            .with_extension(self.spec.format.extension())
+                            .display()
+                            .to_string();
+                    }
 
-    if already_generated.contains(&buf) {
-        return gen_token(rng, character_set, already_generated, token_length);
-    }
+                    if let OutputFormat::Sql { table } = &mut self.spec.format {
+                        ui.label("Table: ");
+                        ui.add(egui::TextEdit::singleline(table).desired_width(120.0));
+                    }
+                });
 
-    Ok(buf)
-}
+                ui.horizontal(|ui| {
+                    if ui.button("Select destination...").clicked() {
+                        let extension = self.spec.format.extension();
+                        let file_dialog = rfd::FileDialog::new()
+                            .add_filter(self.spec.format.to_string(), &[extension]);
 
-impl eframe::App for RandomizerApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("Ticket Randomizer");
-            ui.checkbox(&mut self.capital_letters, "Capital Letters (A-Z)");
-            ui.checkbox(&mut self.lowercase_letters, "Lowercase Letters (a-z)");
-            ui.checkbox(&mut self.numbers, "Number (0-9)");
-            ui.checkbox(&mut self.specials, "Specials (,.;:\"'!%#)");
-
-            ui.horizontal(|ui| {
-                let rejected_chars_label = ui.label("Excluded Characters: ");
-                ui.text_edit_singleline(&mut self.rejected_chars)
-                    .labelled_by(rejected_chars_label.id);
-            });
+                        if let Some(path) = file_dialog.save_file() {
+                            self.file_path = Some(path.display().to_string());
+                        }
+                    }
 
-            ui.horizontal(|ui| {
-                let count_label = ui.label("Ticket Count: ");
-                if ui
-                    .text_edit_singleline(&mut self.ticket_count_str)
-                    .labelled_by(count_label.id)
-                    .lost_focus()
-                {
-                    if let Ok(parsed) = self.ticket_count_str.parse::<usize>() {
-                        self.ticket_count = parsed;
-                    } else {
-                        self.ticket_count_str = self.ticket_count.to_string();
+                    if let Some(file_path) = &self.file_path {
+                        ui.label(file_path);
                     }
+                });
+
+                ui.label(format!(
+                    "Current character set {}",
+                    self.spec.build_character_set()
+                ));
+
+                let stats = self.figures().stats;
+                ui.label(format!(
+                    "Entropy: {:.1} bits per ticket",
+                    stats.bits_per_ticket
+                ));
+                if stats.keyspace == u128::MAX {
+                    ui.label(format!("Keyspace: about 2^{:.1}", stats.bits_per_ticket));
+                } else {
+                    ui.label(format!("Keyspace: {}", stats.keyspace));
                 }
-            });
-
-            ui.horizontal(|ui| {
-                let length_label = ui.label("Ticket Length: ");
-                if ui
-                    .text_edit_singleline(&mut self.ticket_length_str)
-                    .labelled_by(length_label.id)
-                    .lost_focus()
+                ui.label(format!(
+                    "Collision chance without deduplication: {}",
+                    format_probability(stats.collision_probability)
+                ));
+                ui.label(format!(
+                    "Guessing a valid ticket takes about 2^{:.1} attempts",
+                    stats.guessing_bits
+                ));
+                if self.spec.builtin_blocklist
+                    || !self.spec.blocked_words.is_empty()
+                    || self.spec.spreadsheet_safe
+                    || self.spec.min_distance > 1
                 {
-                    if let Ok(parsed) = self.ticket_length_str.parse::<usize>() {
-                        self.ticket_length = parsed;
-                    } else {
-                        self.ticket_length_str = self.ticket_length.to_string();
-                    }
+                    ui.label(
+                        "Blocked tickets and the minimum distance aren't counted, \
+                         so fewer tickets are possible than shown",
+                    );
+                }
+                for warning in stats.warnings(&self.thresholds) {
+                    ui.colored_label(ui.visuals().warn_fg_color, warning);
                 }
-            });
 
-            ui.horizontal(|ui| {
-                if ui.button("Select destination...").clicked() {
-                    let file_dialog = rfd::FileDialog::new().add_filter("csv", &["csv"]);
+                ui.collapsing("Warning Thresholds", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Minimum entropy: ");
+                        ui.add(
+                            egui::DragValue::new(&mut self.thresholds.min_bits_per_ticket)
+                                .range(0.0..=256.0)
+                                .suffix(" bits"),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Maximum collision chance: ");
+                        ui.add(
+                            egui::DragValue::new(&mut self.thresholds.max_collision_probability)
+                                .range(0.0..=1.0)
+                                .speed(0.001),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Minimum guessing work: ");
+                        ui.add(
+                            egui::DragValue::new(&mut self.thresholds.min_guessing_bits)
+                                .range(0.0..=256.0)
+                                .suffix(" bits"),
+                        );
+                    });
+                });
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!self.is_processing, egui::Button::new("Submit"))
+                        .clicked()
+                    {
+                        self.start_processing();
+                    }
 
-                    if let Some(path) = file_dialog.save_file() {
-                        self.file_path = Some(path.display().to_string());
+                    if self.is_processing && ui.button("Cancel").clicked() {
+                        self.cancel.store(true, Ordering::Relaxed);
+                    }
+                });
+
+                for message in self.inbox.read(ui) {
+                    match message {
+                        JobMessage::Progress(progress) => self.progress = Some(progress),
+                        JobMessage::Finished(message) => {
+                            self.last_thread_message = message;
+                            self.is_processing = false;
+                            self.progress = None;
+                        }
                     }
                 }
-
-                if let Some(file_path) = &self.file_path {
-                    ui.label(file_path);
+                if self.is_processing {
+                    let progress = self.progress.unwrap_or(Progress {
+                        written: 0,
+                        total: self.spec.ticket_count,
+                        elapsed: Default::default(),
+                    });
+                    ui.add(egui::ProgressBar::new(progress.fraction()).text(progress.to_string()));
                 }
+                ui.label(&self.last_thread_message);
             });
-
-            ui.label(format!(
-                "Current character set {}",
-                self.build_character_set()
-            ));
-
-            if ui
-                .add_enabled(!self.is_processing, egui::Button::new("Submit"))
-                .clicked()
-            {
-                self.start_processing();
-            }
-
-            if let Some(last) = self.inbox.read(ui).last() {
-                self.last_thread_message = last;
-                self.is_processing = false;
-            }
-            ui.label(&self.last_thread_message);
         });
     }
 }
+
+fn format_probability(probability: f64) -> String {
+    if probability > 0.0 && probability < 1e-4 {
+        format!("{:.2e}%", probability * 100.0)
+    } else {
+        format!("{:.2}%", probability * 100.0)
+    }
+}
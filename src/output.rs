@@ -0,0 +1,581 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::Range,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    GenerationMode, Generator, HashAlgorithm, OutputFormat, Progress, TicketSpec,
+    barcode::BarcodeWriter,
+    columns::{Row, format_timestamp},
+    format::RowWriter,
+    hash::TicketHasher,
+    issued::ticket_lines,
+};
+
+/// How often running jobs report their [`Progress`].
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a finished job produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchSummary {
+    /// The seed the batch can be regenerated from.
+    pub seed: u64,
+    pub written: usize,
+    /// Candidates thrown away because they contained a blocked word or
+    /// weren't [`TicketSpec::spreadsheet_safe`].
+    pub rejected: usize,
+    /// The permuted indices the batch used, in
+    /// [`GenerationMode::Permuted`]. Together with the seed they regenerate
+    /// the batch, and the next batch should start at the end of the range.
+    pub indices: Option<Range<u128>>,
+    /// Distinct earlier tickets the batch was kept away from.
+    pub preloaded: usize,
+    pub format: OutputFormat,
+}
+
+impl fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Successfully wrote {} tickets to {} (seed {}",
+            self.written, self.format, self.seed
+        )?;
+        if self.rejected > 0 {
+            write!(f, ", {} candidates rejected", self.rejected)?;
+        }
+        if let Some(indices) = &self.indices {
+            write!(f, ", indices {}..{}", indices.start, indices.end)?;
+        }
+        if self.preloaded > 0 {
+            write!(f, ", avoiding {} earlier tickets", self.preloaded)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Generates the batch described by `spec` and writes it to `file_path` in
+/// [`TicketSpec::format`], with the [`TicketSpec::output_columns`].
+pub fn build_batch(spec: &TicketSpec, file_path: &str) -> Result<BatchSummary, String> {
+    build_batch_with_progress(spec, file_path, &AtomicBool::new(false), |_| {})
+}
+
+/// Like [`build_batch`], but calls `on_progress` every so often while the
+/// batch is written, and stops with an error soon after `cancel` is set. The
+/// batch, [`TicketSpec::registry`], [`TicketSpec::hashes`] and
+/// [`TicketSpec::barcodes`] are all written in full first and only then
+/// moved into place, so a failed or cancelled job never leaves a partial
+/// file behind.
+pub fn build_batch_with_progress(
+    spec: &TicketSpec,
+    file_path: &str,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<BatchSummary, String> {
+    spec.check_output_path(file_path)?;
+    let registry = match &spec.registry {
+        Some(registry) if Path::new(registry).exists() => Some(
+            fs::read_to_string(registry)
+                .map_err(|err| format!("Failed to read registry {registry}: {err}"))?,
+        ),
+        _ => None,
+    };
+
+    let mut generator = match &registry {
+        Some(contents) => {
+            let mut spec = spec.clone();
+            spec.issued_tickets
+                .extend(ticket_lines(contents).map(str::to_owned));
+            Generator::new(&spec)?
+        }
+        None => Generator::new(spec)?,
+    };
+    let seed = generator.seed().expect("Generator::new always seeds");
+
+    let hasher = spec
+        .hashes
+        .as_ref()
+        .map(|hashes| TicketHasher::new(hashes.algorithm))
+        .transpose()?;
+
+    let mut job = Job {
+        generator: &mut generator,
+        spec,
+        hasher,
+        created_at: format_timestamp(SystemTime::now()),
+        cancel,
+        on_progress: &mut on_progress,
+    };
+    let registry_out = match &spec.registry {
+        Some(registry_path) => {
+            let (part, registry_file) = PartFile::create(registry_path)?;
+            let mut registry_writer = BufWriter::new(registry_file);
+            if let Some(contents) = &registry {
+                let separator = if contents.is_empty() || contents.ends_with('\n') {
+                    ""
+                } else {
+                    "\n"
+                };
+                write!(registry_writer, "{contents}{separator}")
+                    .map_err(|err| format!("Failed to write registry: {err}"))?;
+            }
+            Some((part, registry_writer))
+        }
+        None => None,
+    };
+    let (written, staged) = job.write(file_path, registry_out)?;
+    staged.commit()?;
+
+    Ok(BatchSummary {
+        seed,
+        written,
+        rejected: generator.rejected(),
+        indices: match spec.mode {
+            GenerationMode::Permuted { start } => generator.next_index().map(|end| start..end),
+            GenerationMode::Random | GenerationMode::Sharded { .. } => None,
+        },
+        preloaded: generator.preloaded(),
+        format: spec.format.clone(),
+    })
+}
+
+/// A batch being written, with what's needed to report on it and stop it.
+struct Job<'a, P> {
+    generator: &'a mut Generator,
+    spec: &'a TicketSpec,
+    /// Set when [`TicketSpec::hashes`] is.
+    hasher: Option<TicketHasher>,
+    created_at: String,
+    cancel: &'a AtomicBool,
+    on_progress: &'a mut P,
+}
+
+impl<'a, P: FnMut(Progress)> Job<'a, P> {
+    /// What the batch was generated from, for formats that record it.
+    fn summary(&self) -> Vec<(&'static str, String)> {
+        let spec = self.spec;
+        let mut summary = vec![("Character set", spec.check_character_set())];
+        if !spec.template.is_empty() {
+            summary.push(("Template", spec.template.clone()));
+        }
+        let length = spec.positions().map_or(0, |positions| positions.len()) + spec.check_length();
+        summary.push(("Length", length.to_string()));
+        if let Some(check_algorithm) = spec.check_algorithm {
+            summary.push(("Check characters", check_algorithm.to_string()));
+        }
+        summary.push(("Count", spec.ticket_count.to_string()));
+        if let Some(seed) = self.generator.seed() {
+            summary.push(("Seed", seed.to_string()));
+        }
+        summary.push(("Generation", spec.mode.to_string()));
+        summary.push(("Created", self.created_at.clone()));
+
+        summary
+    }
+
+    /// Writes the batch next to `file_path`, its hashes next to
+    /// [`TicketSpec::hashes`] and each ticket on its own line to `registry`
+    /// as well if there are any, ready to be moved into place.
+    fn write(
+        &mut self,
+        file_path: &str,
+        registry: Option<(PartFile, BufWriter<File>)>,
+    ) -> Result<(usize, Staged<'a>), String> {
+        let (batch, file) = PartFile::create(file_path)?;
+        let (hashes, hash_file) = match &self.spec.hashes {
+            Some(hashes) => {
+                let (part, hash_file) = PartFile::create(&hashes.path)?;
+                (Some(part), Some(hash_file))
+            }
+            None => (None, None),
+        };
+        let (registry, mut registry_writer) = registry.unzip();
+
+        let (written, barcodes) = self.write_rows(
+            file,
+            hash_file,
+            registry_writer
+                .as_mut()
+                .map(|registry_writer| registry_writer as &mut dyn Write),
+        )?;
+        // Closed before it's moved, which Windows insists on.
+        drop(registry_writer);
+
+        Ok((
+            written,
+            Staged {
+                registry,
+                batch,
+                hashes,
+                barcodes,
+            },
+        ))
+    }
+
+    /// Writes and finishes every row, and returns how many there were along
+    /// with the finished barcodes. The files are closed when this returns.
+    fn write_rows(
+        &mut self,
+        file: File,
+        hash_file: Option<File>,
+        mut registry: Option<&mut dyn Write>,
+    ) -> Result<(usize, Option<BarcodeWriter<'a>>), String> {
+        let columns = self.spec.output_columns();
+        let summary = self.summary();
+        let mut row_writer = RowWriter::new(
+            &self.spec.format,
+            file,
+            &columns,
+            self.spec.header,
+            &summary,
+        )
+        .map_err(|err| format!("Failed to write to file: {err}"))?;
+
+        let hash_columns = match &self.spec.hashes {
+            Some(hashes) => hashes.columns(&columns),
+            None => Vec::new(),
+        };
+        let mut hash_writer = hash_file
+            .map(|hash_file| {
+                RowWriter::new(
+                    &self.spec.format,
+                    hash_file,
+                    &hash_columns,
+                    self.spec.header,
+                    &summary,
+                )
+            })
+            .transpose()
+            .map_err(|err| format!("Failed to write hash file: {err}"))?;
+        let mut barcode_writer = self
+            .spec
+            .barcodes
+            .as_ref()
+            .map(|barcodes| BarcodeWriter::new(barcodes, self.spec.serial_start()))
+            .transpose()?;
+        // Argon2id takes long enough per ticket to report progress after any
+        // of them.
+        let report_every = match &self.spec.hashes {
+            Some(hashes) if hashes.algorithm == HashAlgorithm::Argon2id => 1,
+            _ => 1024,
+        };
+
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut written = 0;
+        let mut new_token = String::new();
+        let mut hash = String::new();
+        while let Some(result) = self.generator.next_into(&mut new_token) {
+            if self.cancel.load(Ordering::Relaxed) {
+                return Err(format!(
+                    "Cancelled after {written} tickets, nothing was saved"
+                ));
+            }
+
+            result?;
+            let mut row = Row {
+                ticket: &new_token,
+                hash: "",
+                index: written,
+                created_at: &self.created_at,
+            };
+            row_writer
+                .write_row(&columns, &row)
+                .map_err(|err| format!("Failed to write to file: {err}"))?;
+            if let (Some(hasher), Some(hash_writer)) = (&self.hasher, &mut hash_writer) {
+                hasher.hash_into(&new_token, &mut hash)?;
+                row.hash = &hash;
+                hash_writer
+                    .write_row(&hash_columns, &row)
+                    .map_err(|err| format!("Failed to write hash file: {err}"))?;
+            }
+            if let Some(barcode_writer) = &mut barcode_writer {
+                barcode_writer.write(&new_token, written)?;
+            }
+            if let Some(registry) = &mut registry {
+                writeln!(registry, "{new_token}")
+                    .map_err(|err| format!("Failed to write registry: {err}"))?;
+            }
+            written += 1;
+
+            if written % report_every == 0 && last_report.elapsed() >= PROGRESS_INTERVAL {
+                last_report = Instant::now();
+                (self.on_progress)(Progress {
+                    written,
+                    total: self.spec.ticket_count,
+                    elapsed: start.elapsed(),
+                });
+            }
+        }
+
+        // Nothing is moved into place yet, so any of these failing leaves
+        // no output behind.
+        if let Some(barcode_writer) = &mut barcode_writer {
+            barcode_writer.finish()?;
+        }
+        if let Some(hash_writer) = &mut hash_writer {
+            hash_writer
+                .finish()
+                .map_err(|err| format!("Failed to write hash file: {err}"))?;
+        }
+        row_writer
+            .finish()
+            .map_err(|err| format!("Failed to write to file: {err}"))?;
+        if let Some(registry) = &mut registry {
+            registry
+                .flush()
+                .map_err(|err| format!("Failed to write registry: {err}"))?;
+        }
+
+        Ok((written, barcode_writer))
+    }
+}
+
+/// The complete outputs of a job, waiting to be moved into place. Whatever
+/// hasn't been is removed when this is dropped.
+struct Staged<'a> {
+    registry: Option<PartFile>,
+    batch: PartFile,
+    hashes: Option<PartFile>,
+    barcodes: Option<BarcodeWriter<'a>>,
+}
+
+impl Staged<'_> {
+    /// Moves the outputs into place. Renames are all that can fail here. The
+    /// registry goes first, so handed out tickets are always in it, and the
+    /// batch next, since a spreadsheet holding it open is the likeliest
+    /// failure, and the hashes and barcodes are only kept once it's saved.
+    fn commit(self) -> Result<(), String> {
+        let Self {
+            registry,
+            batch,
+            hashes,
+            barcodes,
+        } = self;
+        if let Some(registry) = registry {
+            registry.commit()?;
+        }
+        batch.commit()?;
+        if let Some(hashes) = hashes {
+            hashes.commit()?;
+        }
+        if let Some(barcodes) = barcodes {
+            barcodes.commit()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A file written next to `path` and only moved there by
+/// [`PartFile::commit`]. It's deleted if dropped before that.
+struct PartFile {
+    path: String,
+    part_path: String,
+    committed: bool,
+}
+
+impl PartFile {
+    fn create(path: &str) -> Result<(Self, File), String> {
+        let part_path = format!("{path}.part");
+        let file = File::create(&part_path)
+            .map_err(|err| format!("Failed to create file {part_path}: {err}"))?;
+
+        Ok((
+            Self {
+                path: path.to_owned(),
+                part_path,
+                committed: false,
+            },
+            file,
+        ))
+    }
+
+    fn commit(mut self) -> Result<(), String> {
+        fs::rename(&self.part_path, &self.path)
+            .map_err(|err| format!("Failed to move {} to {}: {err}", self.part_path, self.path))?;
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for PartFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.part_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BarcodeExport, BarcodeFileNames, Column, HashExport, load_issued_tickets};
+
+    #[test]
+    fn cancelled_jobs_leave_no_file() {
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 6,
+            ticket_count: 10,
+            ..Default::default()
+        };
+        let file_path = std::env::temp_dir().join(format!("cancelled-{}.csv", std::process::id()));
+        let file_path = file_path.display().to_string();
+
+        let result = build_batch_with_progress(&spec, &file_path, &AtomicBool::new(true), |_| {});
+        assert!(result.is_err());
+        assert!(fs::metadata(&file_path).is_err());
+        assert!(fs::metadata(format!("{file_path}.part")).is_err());
+    }
+
+    #[test]
+    fn failed_saves_leave_no_outputs() {
+        let dir = std::env::temp_dir().join(format!("failed-save-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("batch.csv")).unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 6,
+            ticket_count: 10,
+            hashes: Some(HashExport {
+                path: path("hashes.csv"),
+                algorithm: HashAlgorithm::Sha256,
+                with_tickets: false,
+            }),
+            barcodes: Some(BarcodeExport {
+                path: path("barcodes.zip"),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let list = |dir: &Path| {
+            let mut names: Vec<_> = fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort_unstable();
+            names
+        };
+
+        // The batch can't replace a directory, which is only found out once
+        // everything else is written.
+        assert!(build_batch(&spec, &path("batch.csv")).is_err());
+        assert_eq!(list(&dir), ["batch.csv"]);
+
+        // Images in a directory are staged just the same, whether or not
+        // the directory is there already.
+        let mut spec = TicketSpec {
+            hashes: None,
+            ..spec
+        };
+        for existing in [false, true] {
+            let barcodes = spec.barcodes.as_mut().unwrap();
+            barcodes.path = path("barcodes");
+            barcodes.file_names = BarcodeFileNames::Serial;
+            if existing {
+                fs::create_dir_all(dir.join("barcodes")).unwrap();
+                fs::write(dir.join("barcodes/11.png"), "earlier").unwrap();
+            }
+            assert!(build_batch(&spec, &path("batch.csv")).is_err());
+            let expected: &[&str] = if existing {
+                &["barcodes", "batch.csv"]
+            } else {
+                &["batch.csv"]
+            };
+            assert_eq!(list(&dir), expected);
+        }
+        assert_eq!(list(&dir.join("barcodes")), ["11.png"]);
+
+        // Nor are earlier images replaced.
+        fs::remove_dir(dir.join("batch.csv")).unwrap();
+        spec.ticket_count = 11;
+        let error = build_batch(&spec, &path("batch.csv")).unwrap_err();
+        assert!(error.contains("11.png already exists"), "{error}");
+        assert_eq!(list(&dir), ["barcodes"]);
+        assert_eq!(fs::read(dir.join("barcodes/11.png")).unwrap(), b"earlier");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn images_are_moved_into_place() {
+        let dir = std::env::temp_dir().join(format!("barcode-dir-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let barcodes = dir.join("barcodes").display().to_string();
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 6,
+            ticket_count: 3,
+            barcodes: Some(BarcodeExport {
+                path: barcodes.clone(),
+                file_names: BarcodeFileNames::Serial,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let batch = dir.join("batch.csv").display().to_string();
+        fs::create_dir_all(&dir).unwrap();
+        build_batch(&spec, &batch).unwrap();
+        // A later batch can add to the same directory.
+        let spec = TicketSpec {
+            columns: vec![Column::Ticket, Column::Serial { start: 4 }],
+            ..spec
+        };
+        build_batch(&spec, &batch).unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&barcodes)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort_unstable();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            names,
+            ["1.png", "2.png", "3.png", "4.png", "5.png", "6.png"]
+        );
+    }
+
+    #[test]
+    fn registry_keeps_waves_apart() {
+        let registry = std::env::temp_dir().join(format!("registry-{}.txt", std::process::id()));
+        let registry = registry.display().to_string();
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 2,
+            ticket_count: 40,
+            registry: Some(registry.clone()),
+            ..Default::default()
+        };
+
+        let mut file_paths = Vec::new();
+        for wave in 0..2 {
+            let file_path =
+                std::env::temp_dir().join(format!("wave-{wave}-{}.csv", std::process::id()));
+            let file_path = file_path.display().to_string();
+            let summary = build_batch(&spec, &file_path).unwrap();
+            assert_eq!(summary.preloaded, 40 * wave);
+            file_paths.push(file_path);
+        }
+
+        let mut tickets = load_issued_tickets(&registry).unwrap();
+        assert_eq!(tickets.len(), 80);
+        for file_path in &file_paths {
+            assert_eq!(load_issued_tickets(file_path).unwrap().len(), 40);
+            let _ = fs::remove_file(file_path);
+        }
+        let _ = fs::remove_file(&registry);
+
+        tickets.sort_unstable();
+        tickets.dedup();
+        assert_eq!(tickets.len(), 80);
+    }
+}
@@ -0,0 +1,153 @@
+//! Collision-free generation by permuting a counter.
+//!
+//! Index `i` of the keyspace is mapped through a keyed Feistel network and
+//! the result is decoded as a ticket, so distinct indices always give
+//! distinct tickets without remembering any of them. The network works on
+//! the smallest even number of bits that covers the keyspace, and cycle
+//! walking re-applies it until the output lands inside the keyspace.
+
+use sha2::{Digest, Sha256};
+
+use crate::template::Position;
+
+const ROUNDS: u8 = 8;
+
+/// Keyed permutation of `0..domain`.
+#[derive(Debug, Clone)]
+pub(crate) struct Permutation {
+    key: u64,
+    domain: u128,
+    half_bits: u32,
+}
+
+impl Permutation {
+    pub(crate) fn new(key: u64, domain: u128) -> Self {
+        let bits = u128::BITS - domain.saturating_sub(1).leading_zeros();
+        let half_bits = bits.div_ceil(2).max(1);
+        Self {
+            key,
+            domain,
+            half_bits,
+        }
+    }
+
+    pub(crate) fn permute(&self, index: u128) -> u128 {
+        debug_assert!(index < self.domain);
+        let mut value = self.encrypt(index);
+        while value >= self.domain {
+            value = self.encrypt(value);
+        }
+
+        value
+    }
+
+    fn encrypt(&self, value: u128) -> u128 {
+        let mask = (1u128 << self.half_bits) - 1;
+        let mut left = value >> self.half_bits;
+        let mut right = value & mask;
+        for round in 0..ROUNDS {
+            let next = left ^ (self.round_function(round, right) & mask);
+            left = right;
+            right = next;
+        }
+
+        (left << self.half_bits) | right
+    }
+
+    /// SHA-256 of the key, round number and half-block, truncated.
+    fn round_function(&self, round: u8, half: u128) -> u128 {
+        let digest = Sha256::new()
+            .chain_update(b"ticket-gen feistel")
+            .chain_update(self.key.to_le_bytes())
+            .chain_update([round])
+            .chain_update(half.to_le_bytes())
+            .finalize();
+
+        u128::from_le_bytes(digest[..16].try_into().unwrap())
+    }
+}
+
+/// Walks the indices of a batch and decodes each permuted index as a ticket.
+#[derive(Debug, Clone)]
+pub(crate) struct Counter {
+    permutation: Permutation,
+    positions: Vec<Position>,
+    next_index: u128,
+}
+
+impl Counter {
+    /// `positions` must multiply out to less than `u128::MAX` tickets.
+    pub(crate) fn new(key: u64, positions: Vec<Position>, start: u128) -> Self {
+        let domain = positions
+            .iter()
+            .map(|position| position.len() as u128)
+            .product();
+
+        Self {
+            permutation: Permutation::new(key, domain),
+            positions,
+            next_index: start,
+        }
+    }
+
+    /// The index the next ticket will be decoded from.
+    pub(crate) fn next_index(&self) -> u128 {
+        self.next_index
+    }
+
+    pub(crate) fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+
+    /// Appends the ticket at the next index to `buf` and moves past it.
+    /// Returns the permuted index, which is the ticket's position among
+    /// every ticket in mixed radix like any other key.
+    pub(crate) fn fill(&mut self, buf: &mut String) -> Result<u128, String> {
+        if self.next_index >= self.permutation.domain {
+            return Err(format!(
+                "Ran out of indices after {}",
+                self.permutation.domain
+            ));
+        }
+
+        let key = self.permutation.permute(self.next_index);
+        self.next_index += 1;
+
+        // Mixed-radix decode, with the last position as the lowest digit.
+        let start = buf.len();
+        let mut value = key;
+        for position in self.positions.iter().rev() {
+            let size = position.len() as u128;
+            buf.insert(start, position[(value % size) as usize]);
+            value /= size;
+        }
+
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permutation_is_a_bijection() {
+        for domain in [1, 2, 3, 10, 17, 64, 1000] {
+            let permutation = Permutation::new(7, domain);
+            let mut seen: Vec<u128> = (0..domain).map(|i| permutation.permute(i)).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..domain).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn different_keys_give_different_orders() {
+        let a: Vec<u128> = (0..100)
+            .map(|i| Permutation::new(1, 100).permute(i))
+            .collect();
+        let b: Vec<u128> = (0..100)
+            .map(|i| Permutation::new(2, 100).permute(i))
+            .collect();
+        assert_ne!(a, b);
+    }
+}
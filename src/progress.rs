@@ -0,0 +1,69 @@
+//! Progress of a running job, for front ends that want to show it.
+
+use std::{fmt, time::Duration};
+
+/// How far a job has got, reported while it runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub written: usize,
+    pub total: usize,
+    /// Time since the job started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Share of the batch written so far, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+
+        self.written as f32 / self.total as f32
+    }
+
+    /// Tickets written per second so far.
+    pub fn rate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+
+        self.written as f64 / seconds
+    }
+
+    /// Estimated time left at the current rate, or `None` before the rate
+    /// is known.
+    pub fn eta(&self) -> Option<Duration> {
+        let rate = self.rate();
+        if rate == 0.0 {
+            return None;
+        }
+
+        let left = self.total.saturating_sub(self.written) as f64 / rate;
+        Some(Duration::from_secs_f64(left))
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} tickets, {:.0} per second",
+            self.written,
+            self.total,
+            self.rate()
+        )?;
+        if let Some(eta) = self.eta() {
+            let seconds = eta.as_secs();
+            write!(
+                f,
+                ", about {}:{:02}:{:02} left",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Reed-Solomon check words over the small Galois fields DataMatrix and
+//! Aztec codes use.
+
+/// GF(2^m), built from its primitive polynomial.
+pub(crate) struct Field {
+    exp: Vec<u16>,
+    log: Vec<u16>,
+}
+
+impl Field {
+    /// The field of `size` elements generated by `polynomial`, whose highest
+    /// bit is `size` itself.
+    pub(crate) fn new(polynomial: u32, size: usize) -> Self {
+        let mut exp = vec![0; size];
+        let mut log = vec![0; size];
+        let mut x = 1u32;
+        for (i, value) in exp.iter_mut().enumerate() {
+            *value = x as u16;
+            if i < size - 1 {
+                log[x as usize] = i as u16;
+            }
+            x <<= 1;
+            if x as usize >= size {
+                x ^= polynomial;
+            }
+        }
+
+        Self { exp, log }
+    }
+
+    fn mul(&self, a: u16, b: u16) -> u16 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        let order = self.exp.len() - 1;
+        self.exp[(self.log[a as usize] as usize + self.log[b as usize] as usize) % order]
+    }
+
+    /// The `count` check words of `data`, using the generator polynomial
+    /// with roots α¹ to α^count, as both symbologies do.
+    pub(crate) fn check_words(&self, data: &[u16], count: usize) -> Vec<u16> {
+        // Highest degree first, so generator[0] is always 1.
+        let mut generator = vec![1u16];
+        for i in 1..=count {
+            let root = self.exp[i % (self.exp.len() - 1)];
+            let mut next = generator.clone();
+            next.push(0);
+            for (j, &coefficient) in generator.iter().enumerate() {
+                next[j + 1] ^= self.mul(coefficient, root);
+            }
+            generator = next;
+        }
+
+        let mut remainder = vec![0u16; count];
+        for &word in data {
+            let factor = word ^ remainder[0];
+            remainder.rotate_left(1);
+            remainder[count - 1] = 0;
+            for (value, &coefficient) in remainder.iter_mut().zip(&generator[1..]) {
+                *value ^= self.mul(factor, coefficient);
+            }
+        }
+
+        remainder
+    }
+}
@@ -0,0 +1,61 @@
+//! Seeded randomness whose output only depends on the seed.
+//!
+//! Batches are drawn from ChaCha20 keyed with the seed as little-endian
+//! bytes followed by 24 zero bytes, on stream 0, or on stream `i + 1` for
+//! chunk `i` of a sharded batch. Every random choice goes through the
+//! helpers below instead of `rand`'s distributions, whose algorithms may
+//! change between releases, so the same spec and seed keep producing the
+//! same tickets.
+
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// Builds the generator for `seed` as described in the module docs.
+pub(crate) fn seeded(seed: u64) -> ChaCha20Rng {
+    let mut key = [0u8; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    ChaCha20Rng::from_seed(key)
+}
+
+/// Like [`seeded`], but on `stream` instead of stream 0.
+pub(crate) fn seeded_stream(seed: u64, stream: u64) -> ChaCha20Rng {
+    let mut rng = seeded(seed);
+    rng.set_stream(stream);
+    rng
+}
+
+/// A fresh seed for batches that didn't ask for one.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// Uniform index below `len`, rejecting the top of the `u64` range that
+/// would otherwise bias smaller indices.
+pub(crate) fn uniform_index(rng: &mut impl RngCore, len: usize) -> usize {
+    let len = len as u64;
+    let zone = u64::MAX - (u64::MAX - len + 1) % len;
+    loop {
+        let value = rng.next_u64();
+        if value <= zone {
+            return (value % len) as usize;
+        }
+    }
+}
+
+/// Uniform value below `bound`, like [`uniform_index`] but drawing 128 bits.
+pub(crate) fn uniform_below(rng: &mut impl RngCore, bound: u128) -> u128 {
+    let zone = u128::MAX - (u128::MAX - bound + 1) % bound;
+    loop {
+        let value = (rng.next_u64() as u128) << 64 | rng.next_u64() as u128;
+        if value <= zone {
+            return value % bound;
+        }
+    }
+}
+
+/// Fisher-Yates shuffle, swapping from the back.
+pub(crate) fn shuffle<T>(rng: &mut impl RngCore, items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, uniform_index(rng, i + 1));
+    }
+}
@@ -0,0 +1,374 @@
+//! Random generation split across threads for very large batches.
+//!
+//! Candidates are drawn in chunks of [`CHUNK_SIZE`], chunk `i` from the
+//! seed's ChaCha20 stream `i + 1`, so a chunk's contents don't depend on
+//! which thread drew it. The issued set is split into shards by key, and
+//! each shard is checked by its own thread, walking the chunks in order.
+//! The first occurrence of a ticket always wins, and candidates are used
+//! in chunk order however many chunks a round drew, so a seed gives the
+//! same batch, rejection count and errors with any number of threads.
+
+use std::{collections::VecDeque, thread};
+
+use crate::{
+    GenerationMode,
+    blocklist::Blocklist,
+    generator::{CheckCharacter, Layout, Scratch, draw},
+    issued::IssuedSet,
+    rng::seeded_stream,
+};
+
+/// Candidates per chunk. Changing it changes every sharded batch.
+const CHUNK_SIZE: usize = 16_384;
+
+/// Draws and deduplicates chunks a round at a time, one chunk per thread, or
+/// fewer when fewer tickets are still wanted.
+#[derive(Debug)]
+pub(crate) struct Shards {
+    seed: u64,
+    /// One copy of the layout per thread.
+    layouts: Vec<Layout>,
+    /// One part of the issued set per thread.
+    issued: Vec<IssuedSet>,
+    next_chunk: u64,
+    ready: VecDeque<Chunk>,
+    /// The next candidate to look at in the front chunk of `ready`.
+    cursor: usize,
+}
+
+impl Shards {
+    /// `threads` of 0 uses one thread per core, up to
+    /// [`GenerationMode::MAX_THREADS`].
+    pub(crate) fn new(
+        seed: u64,
+        layout: Layout,
+        threads: usize,
+        capacity: usize,
+        key_space: Option<u128>,
+    ) -> Self {
+        let threads = thread_count(threads, || {
+            thread::available_parallelism().map_or(1, |threads| threads.get())
+        });
+
+        Self {
+            seed,
+            layouts: vec![layout; threads],
+            issued: (0..threads)
+                .map(|_| IssuedSet::new(0, capacity / threads, key_space))
+                .collect(),
+            next_chunk: 0,
+            ready: VecDeque::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Marks a ticket from an earlier batch as issued, returning whether it
+    /// wasn't already.
+    pub(crate) fn preload(&mut self, ticket: &str, key: u128) -> bool {
+        let shard = (key % self.issued.len() as u128) as usize;
+        let issued = &mut self.issued[shard];
+        if !issued.is_available(ticket, key) {
+            return false;
+        }
+
+        issued.insert(ticket, key);
+        true
+    }
+
+    /// Writes the next unique ticket into `buf` and returns its key, giving
+    /// up after `budget` candidates in a row were duplicates or blocked.
+    /// `wanted` is how many tickets the batch still needs, this one
+    /// included, so a round doesn't draw far more chunks than that.
+    pub(crate) fn next_into(
+        &mut self,
+        check: Option<&CheckCharacter>,
+        blocklist: Option<&Blocklist>,
+        rejected: &mut usize,
+        budget: usize,
+        wanted: usize,
+        buf: &mut String,
+    ) -> Result<u128, String> {
+        let mut misses = 0;
+        loop {
+            if let Some(chunk) = self.ready.front() {
+                while self.cursor < chunk.keys.len() {
+                    let i = self.cursor;
+                    self.cursor += 1;
+                    let blocked = chunk.blocked_before[i] as usize;
+                    *rejected += blocked;
+                    misses += blocked;
+                    if misses >= budget {
+                        return Err(give_up(budget));
+                    }
+                    if chunk.accepted[i] {
+                        buf.clear();
+                        buf.push_str(chunk.ticket(i));
+                        return Ok(chunk.keys[i]);
+                    }
+                    misses += 1;
+                }
+
+                *rejected += chunk.blocked_after;
+                misses += chunk.blocked_after;
+                self.ready.pop_front();
+                self.cursor = 0;
+                if misses >= budget {
+                    return Err(give_up(budget));
+                }
+                continue;
+            }
+
+            self.draw_round(check, blocklist, wanted)?;
+        }
+    }
+
+    /// Draws the next chunks, enough for `wanted` tickets if none were
+    /// rejected but at most one per thread. Chunk `i` always comes from the
+    /// same stream, so how many a round draws doesn't change the batch.
+    fn draw_round(
+        &mut self,
+        check: Option<&CheckCharacter>,
+        blocklist: Option<&Blocklist>,
+        wanted: usize,
+    ) -> Result<(), String> {
+        let seed = self.seed;
+        let first = self.next_chunk;
+        let shards = self.issued.len();
+        let count = wanted.div_ceil(CHUNK_SIZE).clamp(1, self.layouts.len());
+        self.next_chunk += count as u64;
+
+        let mut chunks = thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .layouts
+                .iter_mut()
+                .take(count)
+                .zip(first..)
+                .map(|(layout, index)| {
+                    scope.spawn(move || Chunk::draw(seed, index, layout, check, blocklist, shards))
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("chunk worker panicked"))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        let duplicates = thread::scope(|scope| {
+            let chunks = &chunks;
+            let workers: Vec<_> = self
+                .issued
+                .iter_mut()
+                .enumerate()
+                .map(|(shard, issued)| {
+                    scope.spawn(move || {
+                        let mut duplicates = Vec::new();
+                        for (c, chunk) in chunks.iter().enumerate() {
+                            for &i in &chunk.by_shard[shard] {
+                                let (ticket, key) = (chunk.ticket(i), chunk.keys[i]);
+                                if issued.is_available(ticket, key) {
+                                    issued.insert(ticket, key);
+                                } else {
+                                    duplicates.push((c, i));
+                                }
+                            }
+                        }
+                        duplicates
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("shard worker panicked"))
+                .collect::<Vec<_>>()
+        });
+
+        for (c, i) in duplicates {
+            chunks[c].accepted[i] = false;
+        }
+        self.ready.extend(chunks);
+
+        Ok(())
+    }
+}
+
+/// The candidates drawn from one stream, stored back to back.
+#[derive(Debug)]
+struct Chunk {
+    text: String,
+    ends: Vec<usize>,
+    keys: Vec<u128>,
+    /// Indices of the candidates each shard is responsible for.
+    by_shard: Vec<Vec<usize>>,
+    accepted: Vec<bool>,
+    /// How many candidates the blocklist threw away right before each kept
+    /// one, and after the last.
+    blocked_before: Vec<u32>,
+    blocked_after: usize,
+}
+
+impl Chunk {
+    fn draw(
+        seed: u64,
+        index: u64,
+        layout: &mut Layout,
+        check: Option<&CheckCharacter>,
+        blocklist: Option<&Blocklist>,
+        shards: usize,
+    ) -> Result<Self, String> {
+        let mut rng = seeded_stream(seed, index + 1);
+        let mut chunk = Chunk {
+            text: String::new(),
+            ends: Vec::with_capacity(CHUNK_SIZE),
+            keys: Vec::with_capacity(CHUNK_SIZE),
+            by_shard: vec![Vec::new(); shards],
+            accepted: Vec::with_capacity(CHUNK_SIZE),
+            blocked_before: Vec::with_capacity(CHUNK_SIZE),
+            blocked_after: 0,
+        };
+
+        let mut scratch = Scratch::default();
+        let mut buf = String::new();
+        for _ in 0..CHUNK_SIZE {
+            buf.clear();
+            let Some(key) = draw(&mut rng, layout, check, blocklist, &mut scratch, &mut buf)?
+            else {
+                chunk.blocked_after += 1;
+                continue;
+            };
+
+            chunk.by_shard[(key % shards as u128) as usize].push(chunk.keys.len());
+            chunk.text.push_str(&buf);
+            chunk.ends.push(chunk.text.len());
+            chunk.keys.push(key);
+            chunk.accepted.push(true);
+            chunk.blocked_before.push(chunk.blocked_after as u32);
+            chunk.blocked_after = 0;
+        }
+
+        Ok(chunk)
+    }
+
+    fn ticket(&self, i: usize) -> &str {
+        let start = if i == 0 { 0 } else { self.ends[i - 1] };
+        &self.text[start..self.ends[i]]
+    }
+}
+
+/// `threads`, or `cores()` capped at [`GenerationMode::MAX_THREADS`] when
+/// it's 0.
+fn thread_count(threads: usize, cores: impl FnOnce() -> usize) -> usize {
+    if threads == 0 {
+        cores().min(GenerationMode::MAX_THREADS)
+    } else {
+        threads
+    }
+}
+
+fn give_up(budget: usize) -> String {
+    format!("Could not find an unused ticket after {budget} attempts")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CHUNK_SIZE, Shards, thread_count};
+    use crate::{
+        GenerationMode, TicketSpec,
+        generator::{Generator, Layout},
+    };
+
+    /// The tickets of `spec` with each thread count, and how many candidates
+    /// were rejected.
+    fn with_thread_counts(spec: &TicketSpec) -> Vec<(Result<Vec<String>, String>, usize)> {
+        [1, 2, 7]
+            .into_iter()
+            .map(|threads| {
+                let spec = TicketSpec {
+                    mode: GenerationMode::Sharded { threads },
+                    ..spec.clone()
+                };
+                let mut generator = Generator::new(&spec).unwrap();
+                let tickets = generator.by_ref().collect();
+                (tickets, generator.rejected())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_full_key_space_ignores_thread_count() {
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 4,
+            ticket_count: 10_000,
+            seed: Some(5),
+            ..Default::default()
+        };
+
+        let batches = with_thread_counts(&spec);
+        assert_eq!(batches[0].0.as_ref().unwrap().len(), 10_000);
+        assert!(batches.iter().all(|batch| *batch == batches[0]));
+    }
+
+    #[test]
+    fn rejections_ignore_thread_count() {
+        let spec = TicketSpec {
+            capital_letters: true,
+            ticket_length: 2,
+            ticket_count: 600,
+            blocked_words: vec!["A".to_owned()],
+            seed: Some(9),
+            ..Default::default()
+        };
+
+        let batches = with_thread_counts(&spec);
+        let (tickets, rejected) = &batches[0];
+        assert!(tickets.as_ref().unwrap().iter().all(|t| !t.contains('A')));
+        assert!(*rejected > 0);
+        assert!(batches.iter().all(|batch| *batch == batches[0]));
+
+        // Candidates drawn after the last ticket don't count.
+        let fewer = with_thread_counts(&TicketSpec {
+            ticket_count: 10,
+            ..spec
+        });
+        assert!(fewer[0].1 < *rejected);
+        assert!(fewer.iter().all(|batch| *batch == fewer[0]));
+    }
+
+    #[test]
+    fn small_batches_draw_only_the_chunks_they_need() {
+        let spec = TicketSpec {
+            numbers: true,
+            ticket_length: 8,
+            ticket_count: 5,
+            ..Default::default()
+        };
+        let layout = Layout::Positions(spec.positions().unwrap());
+        let mut shards = Shards::new(3, layout, 8, 5, None);
+        let mut buf = String::new();
+
+        for wanted in (1..=5).rev() {
+            buf.clear();
+            shards
+                .next_into(None, None, &mut 0, 100, wanted, &mut buf)
+                .unwrap();
+        }
+        assert_eq!(shards.next_chunk, 1);
+
+        // Bigger batches draw what they need, at most one chunk per thread.
+        shards.ready.clear();
+        buf.clear();
+        shards
+            .next_into(None, None, &mut 0, 100, 3 * CHUNK_SIZE, &mut buf)
+            .unwrap();
+        assert_eq!(shards.next_chunk, 4);
+    }
+
+    #[test]
+    fn one_thread_per_core_is_capped() {
+        assert_eq!(thread_count(0, || 8), 8);
+        assert_eq!(thread_count(0, || 1024), GenerationMode::MAX_THREADS);
+        assert_eq!(thread_count(3, || 1024), 3);
+    }
+}
@@ -0,0 +1,1066 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    BarcodeExport, BarcodeFileNames, CheckAlgorithm, ClassLimits, Column, HashExport, OutputFormat,
+    blocklist::{BUILTIN_BLOCKLIST, Blocklist},
+    charset::{BUILTIN_CLASSES, CONFUSABLES, CustomClass},
+    columns::serial,
+    format::XLSX_MAX_ROWS,
+    limits::ClassSampler,
+    spreadsheet::is_spreadsheet_safe,
+    template::{Position, parse_template},
+};
+
+/// Longest ticket that can be generated without a template, which keeps the
+/// class limit calculations cheap.
+const MAX_TICKET_LENGTH: usize = 1024;
+
+/// Everything needed to describe a batch of tickets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TicketSpec {
+    pub capital_letters: bool,
+    pub lowercase_letters: bool,
+    pub numbers: bool,
+    pub specials: bool,
+    /// Extra classes merged after the built-in ones. Disabled classes are
+    /// kept so they can be switched back on but contribute nothing.
+    pub custom_classes: Vec<CustomClass>,
+    /// Minimum and maximum character counts keyed by class name, matching
+    /// [`CharacterClass::name`](crate::CharacterClass) for the built-in
+    /// classes. Limits on a class that isn't selected are an error, unless
+    /// they leave it unrestricted, and limits can't be combined with a
+    /// template.
+    pub class_limits: BTreeMap<String, ClassLimits>,
+    /// Characters removed from the character set after the classes above are merged.
+    pub rejected_chars: String,
+    /// Also removes every character in [`CONFUSABLES`] so tickets are easy to
+    /// read and type.
+    pub human_friendly: bool,
+    /// Ignored when a template is set, since the template fixes the length.
+    pub ticket_length: usize,
+    pub ticket_count: usize,
+    /// Optional pattern describing each position of the ticket. When empty,
+    /// every position draws from the merged character set instead.
+    ///
+    /// - `#` is a digit, `A` a capital letter and `a` a lowercase letter
+    /// - `?` is any character from the merged character set
+    /// - `[...]` lists the allowed characters, with ranges like `[A-Z0-9]`
+    /// - `{n}` repeats the previous character or class `n` times, including
+    ///   any repetition already applied to it, so `A{2}{3}` is six letters
+    /// - `\` makes the next character literal, and any other character is
+    ///   copied into the ticket as is
+    ///
+    /// Excluded characters are removed from every class but never from
+    /// literals.
+    pub template: String,
+    /// Appends check characters computed with this algorithm to every
+    /// ticket, two for the ISO 7064 pure systems like MOD 97-10 and one for
+    /// the rest. See [`CheckAlgorithm::check_length`].
+    pub check_algorithm: Option<CheckAlgorithm>,
+    /// Seed for the batch, or `None` to pick a random one. The same spec and
+    /// seed always produce the same tickets: they are drawn from ChaCha20
+    /// keyed with the seed's little-endian bytes followed by 24 zero bytes,
+    /// using rejection sampling on 64-bit outputs for every choice.
+    pub seed: Option<u64>,
+    /// Rejects tickets containing a word from [`BUILTIN_BLOCKLIST`].
+    ///
+    /// [`BUILTIN_BLOCKLIST`]: crate::BUILTIN_BLOCKLIST
+    pub builtin_blocklist: bool,
+    /// Extra words to reject, usually read with
+    /// [`load_wordlist`](crate::load_wordlist). Leetspeak spellings of every
+    /// blocked word are rejected too.
+    pub blocked_words: Vec<String>,
+    /// Draws a new ticket in place of any that a spreadsheet would misread
+    /// when it opens the file, the same way blocked words are redrawn.
+    /// Tickets are regenerated rather than escaped, so they read the same in
+    /// every format and in the registry. Rejected are tickets that:
+    ///
+    /// - start with `=`, `+`, `-` or `@`, which makes them a formula, or with
+    ///   a tab, carriage return, `'` or `"`
+    /// - look like a number, date or time, like `000123`, `1E10`, `12.5%`,
+    ///   `12.`, `12/31` or `Mar-1`. Only English month names are recognized,
+    ///   so dates a spreadsheet set to another language reads, like `1-Mai`,
+    ///   still get through
+    /// - are `TRUE` or `FALSE` in any case, or an error value like `#N/A`
+    ///
+    /// Quotes elsewhere in a ticket are safe, since every format escapes
+    /// them. Can't be used when every ticket is made of digits only.
+    ///
+    /// Batch IDs, ticket types and constant columns can't be redrawn, so
+    /// the batch is refused if any of them would be misread the same way.
+    pub spreadsheet_safe: bool,
+    /// Every pair of tickets in the batch differs in at least this many
+    /// positions (their Hamming distance), so a single typo can't turn one
+    /// valid ticket into another when it is 2 or more. 0 and 1 only require tickets to be unique.
+    pub min_distance: usize,
+    pub mode: GenerationMode,
+    /// Tickets from earlier batches that new ones must not repeat, usually
+    /// read with [`load_issued_tickets`](crate::load_issued_tickets). New
+    /// tickets also keep [`TicketSpec::min_distance`] from them.
+    pub issued_tickets: Vec<String>,
+    /// Text file listing every ticket issued so far, one per line. Its
+    /// tickets are avoided like [`TicketSpec::issued_tickets`], and
+    /// [`build_batch`](crate::build_batch) adds each new batch to it. It is
+    /// created by the first batch if it doesn't exist yet.
+    pub registry: Option<String>,
+    /// Starts the file with a row of column names, in formats that have one.
+    pub header: bool,
+    /// What each row holds, in order. Tickets are the only column when this
+    /// is empty, and come first when it doesn't list [`Column::Ticket`].
+    pub columns: Vec<Column>,
+    pub format: OutputFormat,
+    /// Also writes a salted hash of every ticket to a separate file, for a
+    /// server that shouldn't store the tickets themselves.
+    pub hashes: Option<HashExport>,
+    /// Also saves a barcode of every ticket.
+    pub barcodes: Option<BarcodeExport>,
+}
+
+/// How tickets are picked from every ticket the spec allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GenerationMode {
+    /// Draw tickets at random and redraw any that were already issued.
+    #[default]
+    Random,
+    /// Decode the indices `start..` of a keyed permutation of the keyspace
+    /// instead. Tickets are unique without remembering the issued ones, and
+    /// the seed and index range are enough to regenerate a batch or carry on
+    /// after it. Can't be combined with class limits or a minimum distance.
+    Permuted { start: u128 },
+    /// Draw tickets at random on `threads` threads, or one per core when 0,
+    /// up to [`GenerationMode::MAX_THREADS`]. Any thread count gives the
+    /// same batch for a seed, but a different one than
+    /// [`GenerationMode::Random`]. Can't be combined with a minimum distance.
+    Sharded { threads: usize },
+}
+
+impl GenerationMode {
+    /// Most threads [`GenerationMode::Sharded`] may ask for.
+    pub const MAX_THREADS: usize = 256;
+}
+
+impl fmt::Display for GenerationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationMode::Random => write!(f, "Random"),
+            GenerationMode::Permuted { .. } => write!(f, "Permuted"),
+            GenerationMode::Sharded { .. } => write!(f, "Sharded"),
+        }
+    }
+}
+
+impl TicketSpec {
+    /// The characters a ticket may be built from, with each character
+    /// appearing once even if several selected classes contain it.
+    pub fn build_character_set(&self) -> String {
+        self.selected_classes()
+            .into_iter()
+            .flat_map(|(_, chars)| chars)
+            .collect()
+    }
+
+    /// The selected classes in merge order. Each one only holds the
+    /// characters that no earlier class already contributed, so together
+    /// they partition the character set.
+    pub fn selected_classes(&self) -> Vec<(String, Vec<char>)> {
+        self.classes_without(&self.excluded_chars())
+    }
+
+    /// [`TicketSpec::selected_classes`], with `excluded_chars` kept out
+    /// instead of the spec's own.
+    fn classes_without(&self, excluded_chars: &str) -> Vec<(String, Vec<char>)> {
+        let selected = [
+            self.capital_letters,
+            self.lowercase_letters,
+            self.numbers,
+            self.specials,
+        ];
+        let builtin_classes = BUILTIN_CLASSES
+            .iter()
+            .zip(selected)
+            .filter(|(_, selected)| *selected)
+            .map(|(class, _)| (class.name, class.chars));
+        let custom_classes = self
+            .custom_classes
+            .iter()
+            .filter(|class| class.enabled)
+            .map(|class| (class.name.as_str(), class.chars.as_str()));
+
+        let mut seen = String::new();
+        let mut classes = Vec::new();
+        for (name, chars) in builtin_classes.chain(custom_classes) {
+            let mut members = Vec::new();
+            for c in chars.chars() {
+                if !excluded_chars.contains(c) && !seen.contains(c) {
+                    seen.push(c);
+                    members.push(c);
+                }
+            }
+            classes.push((name.to_owned(), members));
+        }
+
+        classes
+    }
+
+    /// The first serial number, from the [`Column::Serial`] column or 1
+    /// without one.
+    pub(crate) fn serial_start(&self) -> u64 {
+        self.columns
+            .iter()
+            .find_map(|column| match column {
+                Column::Serial { start } => Some(*start),
+                _ => None,
+            })
+            .unwrap_or(1)
+    }
+
+    /// The columns each row is written with.
+    pub fn output_columns(&self) -> Vec<Column> {
+        let mut columns = self.columns.clone();
+        if !columns.contains(&Column::Ticket) {
+            columns.insert(0, Column::Ticket);
+        }
+
+        columns
+    }
+
+    /// The sampler enforcing [`TicketSpec::class_limits`], or `None` when no
+    /// selected class has limits.
+    pub(crate) fn class_sampler(&self) -> Result<Option<ClassSampler>, String> {
+        let classes: Vec<_> = self
+            .selected_classes()
+            .into_iter()
+            .map(|(name, chars)| {
+                let limits = self.class_limits.get(&name).copied().unwrap_or_default();
+                (name, chars, limits)
+            })
+            .collect();
+
+        // A misspelled class would otherwise just go unlimited.
+        if let Some(name) = self.class_limits.iter().find_map(|(name, limits)| {
+            (limits.is_active() && !classes.iter().any(|(class, _, _)| class == name))
+                .then_some(name)
+        }) {
+            let names: Vec<&str> = classes.iter().map(|(name, _, _)| name.as_str()).collect();
+            let expected = match names.split_last() {
+                Some((last, [])) => (*last).to_owned(),
+                Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+                None => "none".to_owned(),
+            };
+            return Err(format!(
+                "Class limits name {name}, which isn't a selected class, expected {expected}"
+            ));
+        }
+
+        if !classes.iter().any(|(_, _, limits)| limits.is_active()) {
+            return Ok(None);
+        }
+
+        if !self.template.is_empty() {
+            return Err(
+                "Class limits can't be combined with a template, use the template to choose how many characters come from each class"
+                    .to_owned(),
+            );
+        }
+
+        ClassSampler::new(classes, self.ticket_length).map(Some)
+    }
+
+    /// Every character that is kept out of tickets.
+    fn excluded_chars(&self) -> String {
+        let mut buf = self.rejected_chars.clone();
+        if self.human_friendly {
+            buf.push_str(CONFUSABLES);
+        }
+
+        buf
+    }
+
+    /// The confusable characters that [`TicketSpec::human_friendly`] removed
+    /// from this spec, not counting ones that were never available or were
+    /// already excluded by hand.
+    pub fn removed_confusables(&self) -> String {
+        if !self.human_friendly {
+            return String::new();
+        }
+
+        // What the spec would allow with only the hand-picked exclusions.
+        let excluded_chars = &self.rejected_chars;
+        let character_set: Vec<char> = self
+            .classes_without(excluded_chars)
+            .into_iter()
+            .flat_map(|(_, chars)| chars)
+            .collect();
+        let positions = self
+            .positions_without(character_set.clone(), excluded_chars)
+            .unwrap_or_default();
+
+        CONFUSABLES
+            .chars()
+            .filter(|c| {
+                character_set.contains(c)
+                    || positions
+                        .iter()
+                        .any(|position| position.len() >= 2 && position.contains(c))
+            })
+            .collect()
+    }
+
+    /// The allowed characters at each position of a ticket.
+    pub(crate) fn positions(&self) -> Result<Vec<Position>, String> {
+        let character_set: Vec<char> = self.build_character_set().chars().collect();
+        self.positions_without(character_set, &self.excluded_chars())
+    }
+
+    fn positions_without(
+        &self,
+        character_set: Vec<char>,
+        excluded_chars: &str,
+    ) -> Result<Vec<Position>, String> {
+        if self.template.is_empty() {
+            Ok(vec![character_set; self.ticket_length])
+        } else {
+            parse_template(&self.template, &character_set, excluded_chars)
+        }
+    }
+
+    /// The characters that can vary between tickets, in the order they first
+    /// appear. This is the character set [`CheckAlgorithm::LuhnModN`] works
+    /// over, see [`TicketSpec::check_ticket`].
+    pub fn check_character_set(&self) -> String {
+        let mut buf = String::new();
+        for position in self.positions().unwrap_or_default() {
+            if position.len() < 2 {
+                continue;
+            }
+            for c in position {
+                if !buf.contains(c) {
+                    buf.push(c);
+                }
+            }
+        }
+
+        buf
+    }
+
+    /// The template literals, which check characters skip when they aren't
+    /// in the algorithm's alphabet.
+    pub fn literal_chars(&self) -> String {
+        let mut buf = String::new();
+        for position in self.positions().unwrap_or_default() {
+            if let [c] = position[..]
+                && !buf.contains(c)
+            {
+                buf.push(c);
+            }
+        }
+
+        buf
+    }
+
+    /// Whether `ticket` ends in the check characters this spec would give it.
+    /// Always false without a [`TicketSpec::check_algorithm`].
+    pub fn check_ticket(&self, ticket: &str) -> bool {
+        self.check_algorithm.is_some_and(|algorithm| {
+            algorithm.validate(ticket, &self.check_character_set(), &self.literal_chars())
+        })
+    }
+
+    /// How many distinct tickets this spec can produce, saturating at
+    /// `u128::MAX`. Specs that can't produce any tickets return 0.
+    pub fn max_ticket_count(&self) -> u128 {
+        match self.class_sampler() {
+            Ok(Some(sampler)) => return sampler.max_ticket_count(),
+            Ok(None) => {}
+            Err(_) => return 0,
+        }
+
+        let Ok(positions) = self.positions() else {
+            return 0;
+        };
+
+        positions
+            .iter()
+            .try_fold(1u128, |count, position| {
+                count.checked_mul(position.len() as u128)
+            })
+            .unwrap_or(u128::MAX)
+    }
+
+    /// What tickets are checked against, or `None` when filtering is off.
+    pub(crate) fn blocklist(&self) -> Option<Blocklist> {
+        if !self.builtin_blocklist && self.blocked_words.is_empty() && !self.spreadsheet_safe {
+            return None;
+        }
+
+        let builtin: &[&str] = if self.builtin_blocklist {
+            BUILTIN_BLOCKLIST
+        } else {
+            &[]
+        };
+        let custom = self.blocked_words.iter().map(String::as_str);
+
+        let blocklist = Blocklist::new(builtin.iter().copied().chain(custom));
+        Some(if self.spreadsheet_safe {
+            blocklist.spreadsheet_safe()
+        } else {
+            blocklist
+        })
+    }
+
+    /// Bits of entropy in each ticket, the base-2 log of
+    /// [`TicketSpec::max_ticket_count`] without saturating. Specs that can't
+    /// produce any tickets return 0.
+    pub fn entropy_bits(&self) -> f64 {
+        match self.class_sampler() {
+            Ok(Some(sampler)) => return sampler.log2_ticket_count(),
+            Ok(None) => {}
+            Err(_) => return 0.0,
+        }
+
+        let Ok(positions) = self.positions() else {
+            return 0.0;
+        };
+        if positions.iter().any(|position| position.is_empty()) {
+            return 0.0;
+        }
+
+        positions
+            .iter()
+            .map(|position| (position.len() as f64).log2())
+            .sum()
+    }
+
+    /// Checks that the batch can actually be generated before any work starts.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.template.is_empty() {
+            if self.build_character_set().is_empty() {
+                return Err("Character set is empty".to_owned());
+            }
+
+            if self.ticket_length == 0 {
+                return Err("Ticket length must be at least 1".to_owned());
+            }
+
+            if self.ticket_length > MAX_TICKET_LENGTH {
+                return Err(format!("Ticket length must be at most {MAX_TICKET_LENGTH}"));
+            }
+        } else if self.positions()?.is_empty() {
+            return Err("Template must contain at least one character".to_owned());
+        }
+
+        if self.ticket_count == 0 {
+            return Err("Ticket count must be at least 1".to_owned());
+        }
+
+        self.class_sampler()?;
+
+        if let Some(check_algorithm) = self.check_algorithm {
+            let character_set = self.check_character_set();
+            let alphabet = check_algorithm.alphabet(&character_set);
+            if alphabet.len() < 2 {
+                return Err(format!(
+                    "{check_algorithm} check characters need at least two characters to choose from"
+                ));
+            }
+            if let Some(c) = character_set.chars().find(|c| !alphabet.contains(c)) {
+                return Err(format!(
+                    "{check_algorithm} check characters can't cover '{c}', tickets may only use {}",
+                    alphabet.iter().collect::<String>()
+                ));
+            }
+        }
+
+        let max_ticket_count = self.max_ticket_count();
+        if self.ticket_count as u128 > max_ticket_count {
+            return Err(format!(
+                "Cannot generate {} unique tickets: at most {max_ticket_count} distinct tickets exist for this spec",
+                self.ticket_count
+            ));
+        }
+
+        if self.min_distance > 1 && self.ticket_count > 1 {
+            let max_ticket_count = self.max_ticket_count_at_distance()?;
+            if self.ticket_count as u128 > max_ticket_count {
+                return Err(format!(
+                    "Cannot generate {} tickets that differ in {} positions: at most {max_ticket_count} can for this spec",
+                    self.ticket_count, self.min_distance
+                ));
+            }
+        }
+
+        if let OutputFormat::Sql { table } = &self.format
+            && table.split('.').any(|part| part.trim().is_empty())
+        {
+            return Err(format!("Invalid SQL table name '{table}'"));
+        }
+
+        for column in &self.columns {
+            if let Column::Constant { header, value } = column
+                && header.trim().is_empty()
+            {
+                return Err(format!(
+                    "The constant column holding '{value}' needs a header"
+                ));
+            }
+        }
+
+        if let Some(last) = self.ticket_count.checked_sub(1) {
+            for column in &self.columns {
+                if let Column::Serial { start } = column {
+                    serial(*start, last)?;
+                }
+            }
+        }
+
+        self.check_paths(None)?;
+
+        if self.columns.contains(&Column::TicketHash) {
+            return Err("Ticket hashes can only be written to a hash file".to_owned());
+        }
+
+        if self
+            .hashes
+            .as_ref()
+            .is_some_and(|hashes| hashes.path.is_empty())
+        {
+            return Err("Choose where to write the ticket hashes".to_owned());
+        }
+
+        if let Some(barcodes) = &self.barcodes {
+            if barcodes.path.is_empty() {
+                return Err("Choose where to save the barcodes".to_owned());
+            }
+
+            if barcodes.module_size == 0 {
+                return Err("Barcode modules must be at least 1 pixel".to_owned());
+            }
+
+            let quiet_zone = barcodes.symbology.quiet_zone();
+            if barcodes.quiet_zone < quiet_zone {
+                return Err(format!(
+                    "{} needs a quiet zone of at least {quiet_zone} modules, not {}",
+                    barcodes.symbology, barcodes.quiet_zone
+                ));
+            }
+
+            self.check_barcodes()?;
+
+            // Names would collide on file systems that ignore case, which
+            // most desktop systems do by default.
+            let character_set = self.check_character_set();
+            if barcodes.file_names == BarcodeFileNames::Ticket
+                && character_set.chars().any(|c| {
+                    c.is_lowercase() && c.to_uppercase().all(|upper| character_set.contains(upper))
+                })
+            {
+                return Err(
+                    "Tickets that only differ in case would overwrite each other's barcodes, name them by serial number instead"
+                        .to_owned(),
+                );
+            }
+        }
+
+        if self.spreadsheet_safe && self.ticket_chars().iter().all(char::is_ascii_digit) {
+            return Err(
+                "Every ticket would be read as a number, add letters to use spreadsheet-safe mode"
+                    .to_owned(),
+            );
+        }
+
+        if self.spreadsheet_safe {
+            for column in &self.columns {
+                let texts = match column {
+                    Column::BatchId(value) | Column::TicketType(value) => vec![value],
+                    Column::Constant { header, value } => vec![header, value],
+                    _ => continue,
+                };
+                if let Some(text) = texts.into_iter().find(|text| !is_spreadsheet_safe(text)) {
+                    return Err(format!(
+                        "A spreadsheet would misread '{text}' in the {} column, change it or turn off spreadsheet-safe mode",
+                        column.header()
+                    ));
+                }
+            }
+        }
+
+        if self.format == OutputFormat::Xlsx
+            && self.ticket_count + usize::from(self.header) > XLSX_MAX_ROWS
+        {
+            return Err(format!(
+                "An Excel sheet holds at most {XLSX_MAX_ROWS} rows, use another format for {} tickets",
+                self.ticket_count
+            ));
+        }
+
+        match self.mode {
+            GenerationMode::Random => {}
+            GenerationMode::Permuted { start } => self.validate_permuted(start)?,
+            GenerationMode::Sharded { threads } => {
+                if threads > GenerationMode::MAX_THREADS {
+                    return Err(format!(
+                        "Sharded generation can use at most {} threads, not {threads}",
+                        GenerationMode::MAX_THREADS
+                    ));
+                }
+                if self.min_distance > 1 {
+                    return Err(
+                        "A minimum distance can't be combined with sharded generation".to_owned(),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the batch at `file_path` doesn't go to the same file as
+    /// the hashes, registry or barcodes. [`TicketSpec::validate`] checks
+    /// those against each other, and [`build_batch`](crate::build_batch) runs
+    /// both.
+    pub fn check_output_path(&self, file_path: &str) -> Result<(), String> {
+        self.check_paths(Some(file_path))
+    }
+
+    /// Every output is written next to its path and moved there at the end,
+    /// so two of them at the same path would overwrite each other.
+    fn check_paths(&self, file_path: Option<&str>) -> Result<(), String> {
+        let outputs = [
+            ("batch", file_path),
+            (
+                "ticket hashes",
+                self.hashes.as_ref().map(|hashes| hashes.path.as_str()),
+            ),
+            ("registry", self.registry.as_deref()),
+            (
+                "barcodes",
+                self.barcodes
+                    .as_ref()
+                    .map(|barcodes| barcodes.path.as_str()),
+            ),
+        ];
+        let outputs: Vec<_> = outputs
+            .into_iter()
+            .filter_map(|(name, path)| Some((name, path.filter(|path| !path.is_empty())?)))
+            .collect();
+        for (i, (name, path)) in outputs.iter().enumerate() {
+            if let Some((other, _)) = outputs[..i]
+                .iter()
+                .find(|(_, other_path)| same_path(path, other_path))
+            {
+                return Err(format!(
+                    "The {other} and the {name} can't both be written to {path}"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every ticket fits in the chosen
+    /// [`Symbology`](crate::Symbology), if barcodes are saved.
+    /// [`TicketSpec::validate`] does this too, but this is cheap enough to
+    /// run as the symbology is picked.
+    pub fn check_barcodes(&self) -> Result<(), String> {
+        let Some(barcodes) = &self.barcodes else {
+            return Ok(());
+        };
+
+        let length = self.positions()?.len() + self.check_length();
+        barcodes
+            .symbology
+            .check(&self.ticket_chars(), length, barcodes.error_correction)
+    }
+
+    /// How many check characters end each ticket.
+    pub(crate) fn check_length(&self) -> usize {
+        self.check_algorithm
+            .map_or(0, |algorithm| algorithm.check_length())
+    }
+
+    /// Every character a ticket may contain, including its check characters.
+    fn ticket_chars(&self) -> Vec<char> {
+        let positions = self.positions().unwrap_or_default();
+        let check_alphabet = self
+            .check_algorithm
+            .map(|algorithm| algorithm.alphabet(&self.check_character_set()))
+            .unwrap_or_default();
+
+        let mut chars: Vec<char> = positions
+            .into_iter()
+            .flatten()
+            .chain(check_alphabet)
+            .collect();
+        chars.sort_unstable();
+        chars.dedup();
+        chars
+    }
+
+    fn validate_permuted(&self, start: u128) -> Result<(), String> {
+        if self.class_sampler()?.is_some() {
+            return Err("Class limits can't be combined with permuted generation".to_owned());
+        }
+
+        if self.min_distance > 1 {
+            return Err("A minimum distance can't be combined with permuted generation".to_owned());
+        }
+
+        let max_ticket_count = self.max_ticket_count();
+        if max_ticket_count == u128::MAX {
+            return Err(
+                "Too many distinct tickets exist for permuted generation, use shorter tickets"
+                    .to_owned(),
+            );
+        }
+
+        let end = start.saturating_add(self.ticket_count as u128);
+        if end > max_ticket_count {
+            return Err(format!(
+                "Cannot generate {} tickets from index {start}: only {max_ticket_count} indices exist for this spec",
+                self.ticket_count
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Singleton bound on how many tickets can be [`TicketSpec::min_distance`]
+    /// apart: dropping any `min_distance - 1` positions must still leave
+    /// every ticket distinct, so the count is at most the product of the
+    /// smallest remaining position sizes.
+    fn max_ticket_count_at_distance(&self) -> Result<u128, String> {
+        let mut sizes: Vec<usize> = self
+            .positions()?
+            .iter()
+            .map(|position| position.len())
+            .collect();
+        // Check characters can add as many differences on top of the body.
+        let length = sizes.len() + self.check_length();
+        if self.min_distance > length {
+            return Err(format!(
+                "Tickets are only {length} characters long, so they can't differ in {} positions",
+                self.min_distance
+            ));
+        }
+
+        sizes.sort_unstable();
+        let kept = (length + 1 - self.min_distance).min(sizes.len());
+
+        Ok(sizes[..kept]
+            .iter()
+            .try_fold(1u128, |count, size| count.checked_mul(*size as u128))
+            .unwrap_or(u128::MAX))
+    }
+}
+
+/// Whether `a` and `b` name the same file, ignoring `.` parts and
+/// trailing separators.
+fn same_path(a: &str, b: &str) -> bool {
+    let normalize = |path: &str| {
+        let path = Path::new(path);
+        std::path::absolute(path)
+            .unwrap_or_else(|_err| path.to_owned())
+            .components()
+            .collect::<PathBuf>()
+    };
+    normalize(a) == normalize(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::charset::{CAPITALS, LOWERS, NUMBERS, SPECIALS};
+
+    /// A small batch of numbers that passes validation.
+    fn spec() -> TicketSpec {
+        TicketSpec {
+            numbers: true,
+            ticket_length: 4,
+            ticket_count: 10,
+            ..Default::default()
+        }
+    }
+
+    fn all_classes() -> TicketSpec {
+        TicketSpec {
+            capital_letters: true,
+            lowercase_letters: true,
+            numbers: true,
+            specials: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merged_character_set_is_every_class_once() {
+        let character_set = all_classes().build_character_set();
+        assert_eq!(
+            character_set,
+            [CAPITALS, LOWERS, NUMBERS, SPECIALS].concat()
+        );
+
+        let mut unique: Vec<char> = character_set.chars().collect();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), character_set.chars().count());
+    }
+
+    #[test]
+    fn merged_character_set_drops_excluded_chars() {
+        let spec = TicketSpec {
+            rejected_chars: "AW0".to_owned(),
+            ..all_classes()
+        };
+        let character_set = spec.build_character_set();
+        assert!(!character_set.contains(['A', 'W', '0']));
+        assert_eq!(character_set.chars().count(), 71 - 3);
+    }
+
+    #[test]
+    fn removed_confusables_skip_hand_picked_and_unused_ones() {
+        let spec = TicketSpec {
+            capital_letters: true,
+            numbers: true,
+            human_friendly: true,
+            rejected_chars: "O".to_owned(),
+            issued_tickets: vec!["ABC123".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(spec.removed_confusables(), "0Q1I5S2Z8B");
+
+        // Template classes count, literals don't.
+        let template = TicketSpec {
+            template: "I-[l1x]".to_owned(),
+            ..spec.clone()
+        };
+        assert_eq!(template.removed_confusables(), "0Q1Il5S2Z8B");
+        assert_eq!(
+            TicketSpec {
+                human_friendly: false,
+                ..spec
+            }
+            .removed_confusables(),
+            ""
+        );
+    }
+
+    #[test]
+    fn limits_on_unselected_classes_are_rejected() {
+        let at_least_one = ClassLimits { min: 1, max: None };
+        let mut limited = TicketSpec {
+            capital_letters: true,
+            class_limits: BTreeMap::from([("Numbers".to_owned(), at_least_one)]),
+            ..spec()
+        };
+        assert!(limited.validate().is_ok());
+
+        limited.class_limits = BTreeMap::from([("Specials".to_owned(), ClassLimits::default())]);
+        assert!(limited.validate().is_ok());
+
+        limited.class_limits = BTreeMap::from([("Number".to_owned(), at_least_one)]);
+        assert_eq!(
+            limited.validate().unwrap_err(),
+            "Class limits name Number, which isn't a selected class, expected Capital Letters or Numbers"
+        );
+
+        limited.class_limits = BTreeMap::from([("Specials".to_owned(), at_least_one)]);
+        assert!(limited.validate().is_err());
+    }
+
+    #[test]
+    fn custom_classes_merge_without_duplicates() {
+        let spec = TicketSpec {
+            numbers: true,
+            custom_classes: vec![
+                CustomClass {
+                    name: "Hex".to_owned(),
+                    chars: "0123456789ABCDEFabcdef".to_owned(),
+                    enabled: true,
+                },
+                CustomClass {
+                    name: "Repeats".to_owned(),
+                    chars: "FFFGG".to_owned(),
+                    enabled: true,
+                },
+                CustomClass {
+                    name: "Off".to_owned(),
+                    chars: "XYZ".to_owned(),
+                    enabled: false,
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(spec.build_character_set(), "0123456789ABCDEFabcdefG");
+    }
+
+    #[test]
+    fn capacity_counts_every_capital() {
+        let spec = TicketSpec {
+            capital_letters: true,
+            ticket_length: 2,
+            ..Default::default()
+        };
+        assert_eq!(spec.max_ticket_count(), 26 * 26);
+    }
+
+    #[test]
+    fn serials_must_fit_in_u64() {
+        let mut serials = TicketSpec {
+            ticket_count: 1,
+            columns: vec![Column::Ticket, Column::Serial { start: u64::MAX }],
+            ..spec()
+        };
+        assert!(serials.validate().is_ok());
+
+        serials.ticket_count = 3;
+        assert_eq!(
+            serials.validate().unwrap_err(),
+            "Serial numbers from 18446744073709551615 run past 18446744073709551615"
+        );
+
+        serials.columns[1] = Column::Serial {
+            start: u64::MAX - 2,
+        };
+        assert!(serials.validate().is_ok());
+    }
+
+    #[test]
+    fn outputs_need_their_own_paths() {
+        let spec = TicketSpec {
+            hashes: Some(HashExport {
+                path: "out/hashes.csv".to_owned(),
+                algorithm: crate::HashAlgorithm::Sha256,
+                with_tickets: false,
+            }),
+            registry: Some("out/registry.txt".to_owned()),
+            ..spec()
+        };
+        assert!(spec.validate().is_ok());
+        assert!(spec.check_output_path("out/batch.csv").is_ok());
+        assert_eq!(
+            spec.check_output_path("./out/hashes.csv").unwrap_err(),
+            "The batch and the ticket hashes can't both be written to out/hashes.csv"
+        );
+
+        let spec = TicketSpec {
+            registry: Some("out//hashes.csv".to_owned()),
+            ..spec
+        };
+        assert_eq!(
+            spec.validate().unwrap_err(),
+            "The ticket hashes and the registry can't both be written to out//hashes.csv"
+        );
+    }
+
+    #[test]
+    fn columns_need_headers() {
+        let mut venue = TicketSpec {
+            columns: vec![
+                Column::Ticket,
+                Column::Constant {
+                    header: "venue".to_owned(),
+                    value: "Main Hall".to_owned(),
+                },
+            ],
+            ..spec()
+        };
+        assert!(venue.validate().is_ok());
+
+        venue.columns[1] = Column::Constant {
+            header: " ".to_owned(),
+            value: "Main Hall".to_owned(),
+        };
+        assert_eq!(
+            venue.validate().unwrap_err(),
+            "The constant column holding 'Main Hall' needs a header"
+        );
+    }
+
+    #[test]
+    fn spreadsheet_safe_checks_column_values() {
+        let mut columns = TicketSpec {
+            capital_letters: true,
+            spreadsheet_safe: true,
+            columns: vec![
+                Column::Ticket,
+                Column::BatchId("B-7".to_owned()),
+                Column::Constant {
+                    header: "venue".to_owned(),
+                    value: "Main Hall".to_owned(),
+                },
+            ],
+            ..spec()
+        };
+        assert!(columns.validate().is_ok());
+
+        columns.columns[1] = Column::BatchId("-1+2".to_owned());
+        assert_eq!(
+            columns.validate().unwrap_err(),
+            "A spreadsheet would misread '-1+2' in the batch_id column, change it or turn off spreadsheet-safe mode"
+        );
+
+        columns.columns[1] = Column::TicketType("VIP".to_owned());
+        columns.columns[2] = Column::Constant {
+            header: "link".to_owned(),
+            value: "=HYPERLINK(\"x\")".to_owned(),
+        };
+        assert!(columns.validate().is_err());
+
+        columns.spreadsheet_safe = false;
+        assert!(columns.validate().is_ok());
+    }
+
+    #[test]
+    fn barcodes_keep_their_quiet_zone() {
+        for symbology in crate::Symbology::ALL {
+            let barcodes = TicketSpec {
+                barcodes: Some(BarcodeExport {
+                    path: "barcodes".to_owned(),
+                    symbology,
+                    quiet_zone: symbology.quiet_zone(),
+                    ..Default::default()
+                }),
+                ..spec()
+            };
+            assert!(barcodes.validate().is_ok(), "{symbology}");
+        }
+
+        let too_narrow = TicketSpec {
+            barcodes: Some(BarcodeExport {
+                path: "barcodes".to_owned(),
+                symbology: crate::Symbology::Code128,
+                quiet_zone: 4,
+                ..Default::default()
+            }),
+            ..spec()
+        };
+        assert_eq!(
+            too_narrow.validate().unwrap_err(),
+            "Code 128 needs a quiet zone of at least 10 modules, not 4"
+        );
+    }
+
+    #[test]
+    fn thread_counts_are_capped() {
+        let most = TicketSpec {
+            mode: GenerationMode::Sharded {
+                threads: GenerationMode::MAX_THREADS,
+            },
+            ..spec()
+        };
+        assert!(most.validate().is_ok());
+
+        let too_many = TicketSpec {
+            mode: GenerationMode::Sharded { threads: 100_000 },
+            ..spec()
+        };
+        assert_eq!(
+            too_many.validate().unwrap_err(),
+            "Sharded generation can use at most 256 threads, not 100000"
+        );
+    }
+}
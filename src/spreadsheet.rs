@@ -0,0 +1,182 @@
+//! Spots tickets that spreadsheets would turn into something else when a
+//! file is opened, so [`TicketSpec::spreadsheet_safe`](crate::TicketSpec)
+//! can draw them again.
+
+/// Characters that make a spreadsheet read a cell as a formula, or that a
+/// spreadsheet may take as a text marker and drop, when a ticket starts with
+/// them.
+const UNSAFE_FIRST_CHARS: &[char] = &['=', '+', '-', '@', '\t', '\r', '\'', '"'];
+
+/// Currency symbols a spreadsheet strips from an amount.
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥'];
+
+/// Whether `ticket` would show up in a spreadsheet exactly as written.
+pub(crate) fn is_spreadsheet_safe(ticket: &str) -> bool {
+    !ticket.starts_with(UNSAFE_FIRST_CHARS)
+        && !looks_like_number(ticket)
+        && !looks_like_month_date(ticket)
+        && !is_error_literal(ticket)
+        && !ticket.eq_ignore_ascii_case("true")
+        && !ticket.eq_ignore_ascii_case("false")
+}
+
+/// Whether a spreadsheet would read `ticket` as a number, date or time.
+///
+/// This is deliberately broad: any digit groups joined by single `.`, `,`,
+/// `/`, `-` or `:` count, since which of them a spreadsheet converts depends
+/// on its locale. They may be wrapped in parentheses, start with a currency
+/// symbol or `.`, end with a `.` or `,`, and end with an exponent like `E10`
+/// or a `%`.
+fn looks_like_number(ticket: &str) -> bool {
+    let mut s = ticket;
+    if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        s = inner;
+    }
+    s = s.strip_prefix(CURRENCY_SYMBOLS).unwrap_or(s);
+    s = s.strip_suffix('%').unwrap_or(s);
+
+    let mantissa = match s.find(['e', 'E']) {
+        Some(i) => {
+            let exponent = &s[i + 1..];
+            let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return false;
+            }
+            &s[..i]
+        }
+        None => s,
+    };
+    let mantissa = mantissa.strip_prefix('.').unwrap_or(mantissa);
+    let mantissa = mantissa.strip_suffix(['.', ',']).unwrap_or(mantissa);
+
+    !mantissa.is_empty()
+        && mantissa
+            .split(['.', ',', '/', '-', ':'])
+            .all(|group| !group.is_empty() && group.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// English month names in full. Their three-letter abbreviations are
+/// matched as prefixes in [`looks_like_month_date`].
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Whether a spreadsheet would read `ticket` as a date with the month
+/// spelled out, like `Mar-1`, `1-Jan`, `5-Mar-24` or `Jan/2024`: two or
+/// three parts joined by `-`, `/` or spaces, one of them an English month
+/// and the others digits. Month names in other languages aren't caught.
+fn looks_like_month_date(ticket: &str) -> bool {
+    let parts: Vec<&str> = ticket
+        .split(['-', '/', ' ', ','])
+        .filter(|part| !part.is_empty())
+        .collect();
+    let is_month = |part: &str| {
+        let part = part.to_ascii_lowercase();
+        MONTHS
+            .iter()
+            .any(|month| *month == part || (part.len() == 3 && month.starts_with(&part)))
+    };
+
+    (2..=3).contains(&parts.len())
+        && parts.iter().filter(|part| is_month(part)).count() == 1
+        && parts
+            .iter()
+            .all(|part| is_month(part) || part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Error values a spreadsheet shows in place of a formula's result.
+const ERROR_LITERALS: [&str; 9] = [
+    "#N/A", "#NUM!", "#REF!", "#NAME?", "#NULL!", "#DIV/0!", "#VALUE!", "#SPILL!", "#CALC!",
+];
+
+/// Whether a spreadsheet would read `ticket` as an error value like `#N/A`,
+/// in any case.
+fn is_error_literal(ticket: &str) -> bool {
+    ERROR_LITERALS
+        .iter()
+        .any(|error| error.eq_ignore_ascii_case(ticket))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formulas_and_numbers_are_unsafe() {
+        for ticket in [
+            "=1+2",
+            "+AB12",
+            "-AB12",
+            "@SUM",
+            "'AB12",
+            "\"AB12",
+            "000123",
+            "1E10",
+            "1e-5",
+            "12.5%",
+            ".5",
+            "12.",
+            "1,",
+            "1.5E3",
+            "1,234",
+            "(42)",
+            "$100",
+            "12/31",
+            "2024-01-05",
+            "12:30",
+            "TRUE",
+            "false",
+        ] {
+            assert!(!is_spreadsheet_safe(ticket), "{ticket}");
+        }
+
+        for ticket in [
+            "AB12", "1E", "E10", "12A", "1..2", "12/", "A=1", "1-AB", "TRUTH", ".", "1.,",
+        ] {
+            assert!(is_spreadsheet_safe(ticket), "{ticket}");
+        }
+    }
+
+    #[test]
+    fn month_names_make_dates() {
+        for ticket in [
+            "Mar-1",
+            "1-Jan",
+            "5-MAR-24",
+            "jan/2024",
+            "Dec 25",
+            "September-3",
+            "May-12",
+        ] {
+            assert!(!is_spreadsheet_safe(ticket), "{ticket}");
+        }
+
+        for ticket in ["Mar", "Mar-A", "MAY1", "Jan-Feb", "1-Marc", "Mar-1-2-3"] {
+            assert!(is_spreadsheet_safe(ticket), "{ticket}");
+        }
+    }
+
+    #[test]
+    fn error_values_are_unsafe() {
+        for ticket in [
+            "#N/A", "#n/a", "#NUM!", "#REF!", "#NAME?", "#NULL!", "#DIV/0!", "#VALUE!",
+        ] {
+            assert!(!is_spreadsheet_safe(ticket), "{ticket}");
+        }
+
+        for ticket in ["#NA", "#REF", "N/A", "#NUM!1"] {
+            assert!(is_spreadsheet_safe(ticket), "{ticket}");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Security figures for a batch, so they can be reviewed before it is
+//! generated.
+
+use crate::TicketSpec;
+
+/// Entropy, keyspace and attack estimates for a [`TicketSpec`].
+///
+/// The figures count every ticket the layout and class limits allow. Words
+/// on the blocklist and tickets closer than
+/// [`TicketSpec::min_distance`] aren't taken out, so with either of them
+/// the true keyspace is smaller and guessing a ticket a little easier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchStats {
+    /// Bits of entropy in each ticket. Check characters add none.
+    pub bits_per_ticket: f64,
+    /// Number of distinct tickets, saturating at `u128::MAX`.
+    pub keyspace: u128,
+    /// Birthday-bound chance that two tickets in the batch would be equal
+    /// if they were drawn without deduplication.
+    pub collision_probability: f64,
+    /// Bits of work an attacker needs to guess any one valid ticket, i.e.
+    /// `log2(keyspace / ticket_count)`.
+    pub guessing_bits: f64,
+}
+
+impl BatchStats {
+    pub fn new(spec: &TicketSpec) -> Self {
+        let bits_per_ticket = spec.entropy_bits();
+        let count = spec.ticket_count as f64;
+
+        // 1 - e^(-n(n-1) / 2N), worked out in log space since N can be far
+        // larger than an f64 can hold.
+        let pairs = count * (count - 1.0) / 2.0;
+        let exponent = if pairs > 0.0 {
+            (pairs.log2() - bits_per_ticket).exp2()
+        } else {
+            0.0
+        };
+
+        Self {
+            bits_per_ticket,
+            keyspace: spec.max_ticket_count(),
+            collision_probability: -(-exponent).exp_m1(),
+            guessing_bits: (bits_per_ticket - count.max(1.0).log2()).max(0.0),
+        }
+    }
+
+    /// Describes every figure that falls on the wrong side of `thresholds`.
+    pub fn warnings(&self, thresholds: &StatsThresholds) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.bits_per_ticket < thresholds.min_bits_per_ticket {
+            warnings.push(format!(
+                "Entropy is below {} bits per ticket",
+                thresholds.min_bits_per_ticket
+            ));
+        }
+
+        if self.collision_probability > thresholds.max_collision_probability {
+            warnings.push(format!(
+                "Collision chance is above {}%",
+                thresholds.max_collision_probability * 100.0
+            ));
+        }
+
+        if self.guessing_bits < thresholds.min_guessing_bits {
+            warnings.push(format!(
+                "Guessing a valid ticket takes fewer than 2^{} attempts",
+                thresholds.min_guessing_bits
+            ));
+        }
+
+        warnings
+    }
+}
+
+/// Limits below which [`BatchStats::warnings`] flags a batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsThresholds {
+    pub min_bits_per_ticket: f64,
+    /// Between 0 and 1.
+    pub max_collision_probability: f64,
+    pub min_guessing_bits: f64,
+}
+
+impl Default for StatsThresholds {
+    fn default() -> Self {
+        Self {
+            min_bits_per_ticket: 40.0,
+            max_collision_probability: 0.5,
+            min_guessing_bits: 30.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(ticket_length: usize, ticket_count: usize) -> BatchStats {
+        BatchStats::new(&TicketSpec {
+            numbers: true,
+            ticket_length,
+            ticket_count,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn counts_follow_the_layout() {
+        let stats = numbers(4, 100);
+        assert_eq!(stats.keyspace, 10_000);
+        assert!((stats.bits_per_ticket - 10_000f64.log2()).abs() < 1e-9);
+        assert!((stats.guessing_bits - 100f64.log2()).abs() < 1e-9);
+        // 1 - e^(-100 * 99 / 2 / 10000)
+        assert!((stats.collision_probability - 0.390_43).abs() < 1e-5);
+
+        let single = numbers(4, 1);
+        assert_eq!(single.collision_probability, 0.0);
+        assert_eq!(single.guessing_bits, single.bits_per_ticket);
+        // More tickets than the keyspace holds can't be guessed any easier.
+        assert_eq!(numbers(2, 1000).guessing_bits, 0.0);
+    }
+
+    #[test]
+    fn huge_keyspaces_saturate() {
+        let stats = BatchStats::new(&TicketSpec {
+            capital_letters: true,
+            ticket_length: 40,
+            ticket_count: 1000,
+            ..Default::default()
+        });
+        assert_eq!(stats.keyspace, u128::MAX);
+        assert!((stats.bits_per_ticket - 40.0 * 26f64.log2()).abs() < 1e-9);
+        assert!(stats.collision_probability < 1e-40);
+    }
+
+    #[test]
+    fn warnings_follow_the_thresholds() {
+        let thresholds = StatsThresholds::default();
+        assert_eq!(numbers(4, 200).warnings(&thresholds).len(), 3);
+        assert!(numbers(20, 100).warnings(&thresholds).is_empty());
+        assert_eq!(
+            numbers(12, 100).warnings(&thresholds),
+            ["Entropy is below 40 bits per ticket"]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Parser for ticket templates such as `EVT-####-AAAA` or `[A-Z]{2}[0-9]{6}`.
+
+use std::{collections::HashSet, iter::Peekable, str::Chars};
+
+use crate::charset::{CAPITALS, LOWERS, NUMBERS};
+
+/// The characters allowed at a single ticket position. Literal characters
+/// are positions with exactly one choice.
+pub(crate) type Position = Vec<char>;
+
+/// Upper bound on the number of positions a template may expand to, so a
+/// stray `{1000000}` can't exhaust memory.
+const MAX_POSITIONS: usize = 1024;
+
+/// Upper bound on the characters a `[...]` class may list, so a range like
+/// `[\0-\u{10FFFF}]` can't stall whoever is parsing it.
+const MAX_CLASS_SIZE: usize = 4096;
+
+/// Expands `template` into one [`Position`] per ticket character.
+///
+/// `character_set` is what `?` draws from, and `rejected_chars` are removed
+/// from every class (but not from literals).
+pub(crate) fn parse_template(
+    template: &str,
+    character_set: &[char],
+    rejected_chars: &str,
+) -> Result<Vec<Position>, String> {
+    let mut positions: Vec<Position> = Vec::new();
+    // Where the positions a `{n}` after them would repeat start. A
+    // repetition repeats everything since, so `A{2}{3}` is six positions.
+    let mut unit = None;
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '{' {
+            unit = Some(positions.len());
+        }
+        match c {
+            '#' => positions.push(class(NUMBERS.chars(), rejected_chars, "#")?),
+            'A' => positions.push(class(CAPITALS.chars(), rejected_chars, "A")?),
+            'a' => positions.push(class(LOWERS.chars(), rejected_chars, "a")?),
+            '?' => {
+                if character_set.is_empty() {
+                    return Err("Template uses ? but the character set is empty".to_owned());
+                }
+                positions.push(character_set.to_vec());
+            }
+            '[' => {
+                let (members, source) = parse_bracket(&mut chars)?;
+                positions.push(class(members.into_iter(), rejected_chars, &source)?);
+            }
+            '{' => {
+                let count = parse_repeat(&mut chars)?;
+                let start =
+                    unit.ok_or("Template repetition {n} must follow a character or class")?;
+                let repeated = positions.split_off(start);
+                if positions.len() + repeated.len().saturating_mul(count) > MAX_POSITIONS {
+                    return Err(format!(
+                        "Template is longer than {MAX_POSITIONS} characters"
+                    ));
+                }
+                for _ in 0..count {
+                    positions.extend(repeated.iter().cloned());
+                }
+            }
+            '\\' => {
+                let literal = chars
+                    .next()
+                    .ok_or("Template ends with an unfinished \\ escape")?;
+                positions.push(vec![literal]);
+            }
+            ']' | '}' => return Err(format!("Unmatched {c} in template")),
+            literal => positions.push(vec![literal]),
+        }
+
+        if positions.len() > MAX_POSITIONS {
+            return Err(format!(
+                "Template is longer than {MAX_POSITIONS} characters"
+            ));
+        }
+    }
+
+    Ok(positions)
+}
+
+/// Builds a class position, dropping duplicates and rejected characters.
+fn class(
+    members: impl Iterator<Item = char>,
+    rejected_chars: &str,
+    source: &str,
+) -> Result<Position, String> {
+    let mut position = Position::new();
+    let mut seen = HashSet::new();
+    for c in members {
+        if !rejected_chars.contains(c) && seen.insert(c) {
+            position.push(c);
+        }
+    }
+
+    if position.is_empty() {
+        return Err(format!(
+            "Template class {source} is empty once excluded characters are removed"
+        ));
+    }
+
+    Ok(position)
+}
+
+/// Parses the body of a `[...]` class after the opening bracket. Returns the
+/// members and the class as written, for error messages.
+fn parse_bracket(chars: &mut Peekable<Chars>) -> Result<(Vec<char>, String), String> {
+    let mut members = Vec::new();
+    let mut source = String::from("[");
+
+    loop {
+        let c = chars.next().ok_or("Unclosed [ in template")?;
+        source.push(c);
+        let c = match c {
+            ']' => break,
+            '\\' => {
+                let escaped = chars.next().ok_or("Unclosed [ in template")?;
+                source.push(escaped);
+                escaped
+            }
+            c => c,
+        };
+
+        // `-` between two characters is a range, anywhere else it's literal.
+        if chars.peek() == Some(&'-') {
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            if let Some(end) = lookahead.next().filter(|end| *end != ']') {
+                chars.next();
+                chars.next();
+                source.push('-');
+                source.push(end);
+                if end < c {
+                    return Err(format!("Template range {c}-{end} is backwards"));
+                }
+                if members.len() + (c..=end).count() > MAX_CLASS_SIZE {
+                    return Err(format!(
+                        "Template class {source}...] lists more than {MAX_CLASS_SIZE} characters"
+                    ));
+                }
+                members.extend(c..=end);
+                continue;
+            }
+        }
+
+        if members.len() == MAX_CLASS_SIZE {
+            return Err(format!(
+                "Template class {source}...] lists more than {MAX_CLASS_SIZE} characters"
+            ));
+        }
+        members.push(c);
+    }
+
+    Ok((members, source))
+}
+
+/// Parses the body of a `{n}` repetition after the opening brace.
+fn parse_repeat(chars: &mut Peekable<Chars>) -> Result<usize, String> {
+    let mut digits = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => digits.push(c),
+            None => return Err("Unclosed { in template".to_owned()),
+        }
+    }
+
+    digits
+        .parse()
+        .map_err(|_err| format!("Template repetition {{{digits}}} is not a number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(template: &str) -> Result<Vec<Position>, String> {
+        parse_template(template, &['x', 'y'], "O0")
+    }
+
+    /// Each position as a string, literals and classes alike.
+    fn shape(template: &str) -> Vec<String> {
+        parse(template)
+            .unwrap()
+            .into_iter()
+            .map(|position| position.into_iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn expands_classes_ranges_and_escapes() {
+        assert_eq!(shape("E-#"), ["E", "-", "123456789"]);
+        assert_eq!(shape("?a")[0], "xy");
+        assert_eq!(shape("A")[0].len(), 25);
+        assert_eq!(shape("[a-dbX-Z]"), ["abcdXYZ"]);
+        assert_eq!(shape("[-a-c]"), ["-abc"]);
+        assert_eq!(shape("[a-]"), ["a-"]);
+        assert_eq!(shape(r"[\]x-z]"), ["]xyz"]);
+        assert_eq!(shape(r"[x\-z]"), ["x-z"]);
+        assert_eq!(shape(r"\#\[\\"), ["#", "[", "\\"]);
+        // Exclusions only apply to classes.
+        assert_eq!(shape("O[MNO]"), ["O", "MN"]);
+    }
+
+    #[test]
+    fn repeats_what_comes_before() {
+        assert_eq!(shape("[ab]{3}"), ["ab", "ab", "ab"]);
+        assert_eq!(shape("X#{0}Y"), ["X", "Y"]);
+        assert_eq!(shape("X{2}{3}").len(), 6);
+        assert_eq!(shape("X{0}{3}Y"), ["Y"]);
+        assert_eq!(shape("XY{2}"), ["X", "Y", "Y"]);
+        assert_eq!(parse("#{1024}").unwrap().len(), 1024);
+    }
+
+    #[test]
+    fn explains_what_is_wrong() {
+        let error = |template| parse(template).unwrap_err();
+        assert_eq!(error("[abc"), "Unclosed [ in template");
+        assert_eq!(error("a]"), "Unmatched ] in template");
+        assert_eq!(error("#}"), "Unmatched } in template");
+        assert_eq!(error("#{3"), "Unclosed { in template");
+        assert_eq!(error("#{x}"), "Template repetition {x} is not a number");
+        assert_eq!(
+            error("{3}"),
+            "Template repetition {n} must follow a character or class"
+        );
+        assert_eq!(error("#\\"), "Template ends with an unfinished \\ escape");
+        assert_eq!(error("[z-a]"), "Template range z-a is backwards");
+        assert_eq!(
+            error("[O0]"),
+            "Template class [O0] is empty once excluded characters are removed"
+        );
+        assert_eq!(error("#{1025}"), "Template is longer than 1024 characters");
+        assert_eq!(
+            error("##{1000}{1000}"),
+            "Template is longer than 1024 characters"
+        );
+        assert_eq!(
+            parse_template("?", &[], "").unwrap_err(),
+            "Template uses ? but the character set is empty"
+        );
+    }
+
+    #[test]
+    fn huge_ranges_are_refused() {
+        assert_eq!(
+            parse("[\0-\u{10FFFF}]").unwrap_err(),
+            "Template class [\0-\u{10FFFF}...] lists more than 4096 characters"
+        );
+        let listed: String = ('\u{100}'..='\u{1100}').collect();
+        assert!(parse(&format!("[{listed}]")).is_err());
+        assert_eq!(shape("[\u{100}-\u{10FF}]")[0].chars().count(), 4096);
+    }
+}